@@ -1,5 +1,14 @@
+use std::fmt;
+
+use crate::XorShift;
+
 const INPUT: &str = include_str!("input/2.txt");
 
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+/// How many characters past what the policy needs a generated password may have
+const MAX_PADDING: usize = 8;
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 struct PasswordLine {
     policy: Policy,
@@ -7,10 +16,185 @@ struct PasswordLine {
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
-struct Policy {
-    min: usize,
-    max: usize,
-    letter: char,
+pub struct Policy {
+    pub min: usize,
+    pub max: usize,
+    pub letter: char,
+}
+
+/// The two ways of reading a policy; part (a) and part (b) of the puzzle respectively
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Interpretation {
+    /// The letter appears between `min` and `max` times (inclusive)
+    RangeCount,
+    /// The letter appears at exactly one of the (1-indexed) positions `min` and `max`
+    Positional,
+}
+
+impl Policy {
+    pub fn is_satisfied_by(&self, password: &str, interpretation: Interpretation) -> bool {
+        match interpretation {
+            Interpretation::RangeCount => {
+                let count = password.chars().filter(|&c| c == self.letter).count();
+                count >= self.min && count <= self.max
+            }
+            Interpretation::Positional => {
+                let fits = password
+                    .chars()
+                    .enumerate()
+                    // restricted just to the characters at the specified indices ...
+                    .filter(|(i, _c)| i + 1 == self.min || i + 1 == self.max)
+                    // count the ones that match the letter in the policy
+                    .filter(|(_i, c)| *c == self.letter)
+                    .count();
+
+                fits == 1
+            }
+        }
+    }
+
+    /// The (1-indexed) positions a positional reading looks at, deduplicated
+    fn positions(&self) -> Vec<usize> {
+        let mut out = vec![self.min, self.max];
+        out.dedup();
+        out.retain(|&p| p > 0);
+        out
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{} {}", self.min, self.max, self.letter)
+    }
+}
+
+fn random_letter(rng: &mut XorShift) -> char {
+    ALPHABET[rng.below(ALPHABET.len())] as char
+}
+
+fn random_letter_except(avoid: char, rng: &mut XorShift) -> char {
+    loop {
+        let c = random_letter(rng);
+        if c != avoid {
+            return c;
+        }
+    }
+}
+
+/// Generates a random lowercase password which satisfies (if `valid`) or violates (if not)
+/// the policy under the given interpretation. Returns None if no such password exists,
+/// e.g. a valid password for a positional policy with no usable positions.
+pub fn generate_password(
+    policy: Policy,
+    interpretation: Interpretation,
+    valid: bool,
+    rng: &mut XorShift,
+) -> Option<String> {
+    let out: Vec<char> = match interpretation {
+        Interpretation::RangeCount => {
+            let count = if valid {
+                if policy.min > policy.max {
+                    return None;
+                }
+                rng.between(policy.min, policy.max)
+            } else if policy.min == 0 || rng.below(2) == 0 {
+                rng.between(policy.max + 1, policy.max + 3)
+            } else {
+                rng.between(0, policy.min - 1)
+            };
+
+            // passwords can't be empty, so pad out a zero-count password
+            let len = (count + rng.between(0, MAX_PADDING)).max(1);
+            let mut chars: Vec<char> = (0..len)
+                .map(|i| {
+                    if i < count {
+                        policy.letter
+                    } else {
+                        random_letter_except(policy.letter, rng)
+                    }
+                })
+                .collect();
+
+            // Fisher-Yates, so the letters aren't all up front
+            for i in (1..chars.len()).rev() {
+                chars.swap(i, rng.below(i + 1));
+            }
+
+            chars
+        }
+        Interpretation::Positional => {
+            let positions = policy.positions();
+
+            // Which of the positions should hold the letter
+            let hits: Vec<bool> = match (valid, positions.len()) {
+                (true, 0) => return None,
+                (true, n) => {
+                    let hit = rng.below(n);
+                    (0..n).map(|i| i == hit).collect()
+                }
+                (false, 2) => {
+                    let both = rng.below(2) == 0;
+                    vec![both, both]
+                }
+                (false, n) => vec![false; n],
+            };
+
+            let needed = positions.iter().copied().max().unwrap_or(1);
+            let len = needed + rng.between(0, MAX_PADDING);
+            let mut chars: Vec<char> = (0..len).map(|_| random_letter(rng)).collect();
+
+            for (&pos, &hit) in positions.iter().zip(hits.iter()) {
+                chars[pos - 1] = if hit {
+                    policy.letter
+                } else {
+                    random_letter_except(policy.letter, rng)
+                };
+            }
+
+            chars
+        }
+    };
+
+    Some(out.into_iter().collect())
+}
+
+/// Generates `count` lines in the puzzle input format, each using a policy picked at random
+/// from `policies`. Policies which can't produce the requested kind of password are skipped.
+pub fn generate_lines(
+    policies: &[Policy],
+    interpretation: Interpretation,
+    valid: bool,
+    count: usize,
+    seed: u64,
+) -> Result<Vec<String>, String> {
+    if policies.is_empty() {
+        return Err("No policies to generate passwords for".to_string());
+    }
+
+    let mut rng = XorShift::new(seed);
+    let mut out = Vec::with_capacity(count);
+
+    while out.len() < count {
+        let start = rng.below(policies.len());
+
+        let generated = (0..policies.len())
+            .map(|offset| policies[(start + offset) % policies.len()])
+            .find_map(|policy| {
+                generate_password(policy, interpretation, valid, &mut rng)
+                    .map(|password| format!("{}: {}", policy, password))
+            })
+            .ok_or_else(|| {
+                format!(
+                    "None of the policies allow a {} password under {:?}",
+                    if valid { "valid" } else { "invalid" },
+                    interpretation
+                )
+            })?;
+
+        out.push(generated);
+    }
+
+    Ok(out)
 }
 
 mod parse {
@@ -114,19 +298,13 @@ mod parse {
 fn run_2a_with_input(input: &str) -> usize {
     let lines = parse::parse(input);
 
-    let mut good = 0;
-    for line in lines {
-        let count = line
-            .password
-            .chars()
-            .filter(|&c| c == line.policy.letter)
-            .count();
-        if count >= line.policy.min && count <= line.policy.max {
-            good += 1;
-        }
-    }
-
-    good
+    lines
+        .iter()
+        .filter(|line| {
+            line.policy
+                .is_satisfied_by(&line.password, Interpretation::RangeCount)
+        })
+        .count()
 }
 
 pub fn run_2a() -> usize {
@@ -136,30 +314,53 @@ pub fn run_2a() -> usize {
 fn run_2b_with_input(input: &str) -> usize {
     let lines = parse::parse(input);
 
-    let mut good = 0;
-    for line in lines {
-        let fits = line
-            .password
-            .chars()
-            .enumerate()
-            // restricted just to the characters at the specified indices ...
-            .filter(|(i, _c)| i + 1 == line.policy.min || i + 1 == line.policy.max)
-            // count the ones that match the letter in the policy
-            .filter(|(_i, c)| *c == line.policy.letter)
-            .count();
-
-        if fits == 1 {
-            good += 1;
-        }
-    }
-
-    good
+    lines
+        .iter()
+        .filter(|line| {
+            line.policy
+                .is_satisfied_by(&line.password, Interpretation::Positional)
+        })
+        .count()
 }
 
 pub fn run_2b() -> usize {
     run_2b_with_input(INPUT)
 }
 
+/// CLI entry point: `2gen <count> <seed> [range|positional] [valid|invalid]`, using the
+/// policies from the puzzle input
+pub fn run_2gen(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 2gen <count> <seed> [range|positional] [valid|invalid]";
+
+    let count = args
+        .first()
+        .and_then(|s| s.parse::<usize>().ok())
+        .ok_or(usage)?;
+    let seed = args
+        .get(1)
+        .and_then(|s| s.parse::<u64>().ok())
+        .ok_or(usage)?;
+    let interpretation = match args.get(2).map(|s| s.as_str()) {
+        None | Some("range") => Interpretation::RangeCount,
+        Some("positional") => Interpretation::Positional,
+        Some(other) => return Err(format!("Unrecognized interpretation {}; {}", other, usage)),
+    };
+    let valid = match args.get(3).map(|s| s.as_str()) {
+        None | Some("valid") => true,
+        Some("invalid") => false,
+        Some(other) => return Err(format!("Unrecognized validity {}; {}", other, usage)),
+    };
+
+    let policies: Vec<Policy> = parse::parse(INPUT)
+        .into_iter()
+        .map(|line| line.policy)
+        .collect();
+
+    let lines = generate_lines(&policies, interpretation, valid, count, seed)?;
+
+    Ok(lines.join("\n"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -177,4 +378,72 @@ mod tests {
     fn sample_2b() {
         assert_eq!(run_2b_with_input(SAMPLE_INPUT), 1);
     }
+
+    #[test]
+    fn generated_passwords_match_policy() {
+        let policies: Vec<Policy> = parse::parse(INPUT)
+            .into_iter()
+            .map(|line| line.policy)
+            .chain(vec![
+                Policy {
+                    min: 0,
+                    max: 0,
+                    letter: 'z',
+                },
+                Policy {
+                    min: 4,
+                    max: 4,
+                    letter: 'q',
+                },
+                Policy {
+                    min: 5,
+                    max: 2,
+                    letter: 'b',
+                },
+            ])
+            .collect();
+
+        let mut rng = XorShift::new(2020);
+
+        for &policy in &policies {
+            for &interpretation in &[Interpretation::RangeCount, Interpretation::Positional] {
+                for &valid in &[true, false] {
+                    for _ in 0..10 {
+                        if let Some(password) =
+                            generate_password(policy, interpretation, valid, &mut rng)
+                        {
+                            assert_eq!(
+                                policy.is_satisfied_by(&password, interpretation),
+                                valid,
+                                "{} / {:?} / {}",
+                                policy,
+                                interpretation,
+                                password
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generated_lines_parse_and_count() {
+        let policies: Vec<Policy> = parse::parse(SAMPLE_INPUT)
+            .into_iter()
+            .map(|line| line.policy)
+            .collect();
+
+        let good = generate_lines(&policies, Interpretation::Positional, true, 50, 7).unwrap();
+        let bad = generate_lines(&policies, Interpretation::Positional, false, 50, 7).unwrap();
+
+        assert_eq!(run_2b_with_input(&good.join("\n")), 50);
+        assert_eq!(run_2b_with_input(&bad.join("\n")), 0);
+
+        // same seed, same output
+        assert_eq!(
+            generate_lines(&policies, Interpretation::Positional, true, 50, 7).unwrap(),
+            good
+        );
+    }
 }
@@ -1,42 +1,391 @@
+use std::fmt;
+
 const INPUT: &str = include_str!("input/3.txt");
 
-fn parse(input: &str) -> Vec<Vec<bool>> {
-    input
-        .lines()
-        .map(|line| {
-            line.chars()
-                .map(|c| match c {
-                    '.' => false,
-                    '#' => true,
-                    other => panic!("Unrecognized character '{}'", other),
-                })
-                .collect::<Vec<bool>>()
-        })
-        .collect()
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SlopeScore {
+    pub dx: usize,
+    pub dy: usize,
+    pub trees: usize,
+}
+
+/// The tree map, which repeats infinitely to the right. Each row is packed into u64 words,
+/// one bit per square, and every row has the same width.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TreeMap {
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    bits: Vec<u64>,
 }
 
-fn count_collisions(grid: &Vec<Vec<bool>>, dx: usize, dy: usize) -> usize {
+impl TreeMap {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether there is a tree at (x, y). Any x is fine since the map repeats horizontally;
+    /// panics if y is past the bottom of the map.
+    pub fn is_tree(&self, x: usize, y: usize) -> bool {
+        assert!(y < self.height, "Row {} is off the map", y);
+
+        let x = x % self.width;
+        let word = self.bits[y * self.words_per_row + x / 64];
+        (word >> (x % 64)) & 1 == 1
+    }
+}
+
+impl fmt::Display for TreeMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in 0..self.height {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            for x in 0..self.width {
+                write!(f, "{}", if self.is_tree(x, y) { '#' } else { '.' })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(input: &str) -> Result<TreeMap, String> {
+    let mut width = None;
+    let mut height = 0;
+    let mut bits = Vec::new();
+
+    for (y, line) in input.lines().enumerate() {
+        let row_width = line.chars().count();
+        let width = *width.get_or_insert(row_width);
+
+        if row_width == 0 {
+            return Err(format!("Row {} is empty", y));
+        } else if row_width != width {
+            return Err(format!(
+                "Row {} has width {}, but the map has width {}",
+                y, row_width, width
+            ));
+        }
+
+        let mut row = vec![0_u64; width.div_ceil(64)];
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                '.' => {}
+                '#' => {
+                    row[x / 64] |= 1 << (x % 64);
+                }
+                other => {
+                    return Err(format!(
+                        "Unrecognized character '{}' at ({}, {})",
+                        other, x, y
+                    ));
+                }
+            }
+        }
+
+        bits.extend(row);
+        height += 1;
+    }
+
+    let width = width.ok_or_else(|| "Tree map has no rows".to_string())?;
+
+    Ok(TreeMap {
+        width,
+        height,
+        words_per_row: width.div_ceil(64),
+        bits,
+    })
+}
+
+/// Counts the trees hit going `dx` right and `dy` down at a time; panics if `dy` is zero,
+/// since the toboggan would never reach the bottom
+pub fn count_collisions(map: &TreeMap, dx: usize, dy: usize) -> usize {
+    assert!(dy > 0, "Slope must move down at least one row");
+
     let mut x = 0;
     let mut y = 0;
 
     let mut collisions = 0;
 
-    while y < grid.len() {
-        if grid[y][x] {
+    while y < map.height() {
+        if map.is_tree(x, y) {
             collisions += 1;
         }
 
-        x = (x + dx) % grid[y].len();
+        x = (x + dx) % map.width();
         y += dy;
     }
 
     collisions
 }
 
+/// Counts the trees hit for every slope with `0 <= dx <= max_dx` and `1 <= dy <= max_dy`,
+/// sorted from fewest trees to most (ties broken by dy, then dx).
+pub fn rank_slopes(map: &TreeMap, max_dx: usize, max_dy: usize) -> Vec<SlopeScore> {
+    let width = map.width();
+
+    let mut out = Vec::with_capacity((max_dx + 1) * max_dy);
+
+    for dy in 1..=max_dy {
+        // The map repeats horizontally, so only dx mod width matters; each distinct
+        // residue is walked at most once per dy no matter how wide the search is
+        let mut cache: Vec<Option<usize>> = vec![None; width];
+
+        for dx in 0..=max_dx {
+            let residue = dx % width;
+            let trees = *cache[residue].get_or_insert_with(|| count_collisions(map, residue, dy));
+
+            out.push(SlopeScore { dx, dy, trees });
+        }
+    }
+
+    out.sort_by_key(|s| (s.trees, s.dy, s.dx));
+    out
+}
+
+/// A slope which moves `right_num / right_den` squares right for every `down` rows. Integer
+/// slopes are the ones with `right_den == 1`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Slope {
+    right_num: usize,
+    right_den: usize,
+    down: usize,
+}
+
+impl Slope {
+    pub fn new(right_num: usize, right_den: usize, down: usize) -> Result<Slope, String> {
+        if right_den == 0 {
+            Err("Slope denominator must be positive".to_string())
+        } else if down == 0 {
+            Err("Slope must move down at least one row".to_string())
+        } else {
+            let g = gcd(right_num, right_den);
+            Ok(Slope {
+                right_num: right_num / g,
+                right_den: right_den / g,
+                down,
+            })
+        }
+    }
+
+    pub fn integer(dx: usize, dy: usize) -> Result<Slope, String> {
+        Slope::new(dx, 1, dy)
+    }
+}
+
+/// One piece of a route: `steps` moves along `slope`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Segment {
+    pub slope: Slope,
+    pub steps: usize,
+}
+
+/// Written back in the `3route` syntax, e.g. `3/2,1x5`
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let slope = &self.slope;
+        write!(f, "{}", slope.right_num)?;
+        if slope.right_den != 1 {
+            write!(f, "/{}", slope.right_den)?;
+        }
+        write!(f, ",{}", slope.down)?;
+        if self.steps != usize::MAX {
+            write!(f, "x{}", self.steps)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct SegmentReport {
+    /// Fewer than the segment asked for, if the route ran off the bottom of the map
+    pub steps_taken: usize,
+    pub collisions: usize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RouteReport {
+    pub segments: Vec<SegmentReport>,
+    pub collisions: usize,
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a.max(1)
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Rides a piecewise route starting from the top left. The exact horizontal position is kept
+/// as a fraction; at each landing row the toboggan is on whichever column the line is over
+/// (the nearest one, ties going right), so an integer slope only ever lands on the squares
+/// `count_collisions` would. The starting square counts toward the first segment.
+pub fn ride_route(map: &TreeMap, segments: &[Segment]) -> RouteReport {
+    // x is x_num / x_den, kept in lowest terms
+    let mut x_num = 0;
+    let mut x_den = 1;
+    let mut y = 0;
+
+    let mut reports = Vec::with_capacity(segments.len());
+
+    for (i, segment) in segments.iter().enumerate() {
+        let mut report = SegmentReport::default();
+        if i == 0 && map.is_tree(0, 0) {
+            report.collisions += 1;
+        }
+
+        let Slope {
+            right_num,
+            right_den,
+            down,
+        } = segment.slope;
+
+        while report.steps_taken < segment.steps && y + down < map.height() {
+            let den = x_den / gcd(x_den, right_den) * right_den;
+            x_num = x_num * (den / x_den) + right_num * (den / right_den);
+            x_den = den;
+
+            let g = gcd(x_num, x_den);
+            x_num /= g;
+            x_den /= g;
+
+            y += down;
+            report.steps_taken += 1;
+
+            // nearest column to x_num / x_den, rounding halves up
+            let x = (2 * x_num + x_den) / (2 * x_den);
+            if map.is_tree(x, y) {
+                report.collisions += 1;
+            }
+        }
+
+        reports.push(report);
+    }
+
+    RouteReport {
+        collisions: reports.iter().map(|r| r.collisions).sum(),
+        segments: reports,
+    }
+}
+
+/// Counts the trees hit following a single slope all the way to the bottom
+pub fn count_collisions_on_slope(map: &TreeMap, slope: Slope) -> usize {
+    ride_route(
+        map,
+        &[Segment {
+            slope,
+            steps: usize::MAX,
+        }],
+    )
+    .collisions
+}
+
+/// The squares the toboggan lands on for a slope, not counting the starting square. The x
+/// coordinates are not wrapped, so they index into the map repeated to the right.
+fn path(map: &TreeMap, dx: usize, dy: usize) -> Vec<(usize, usize)> {
+    (1..)
+        .map(|step| (step * dx, step * dy))
+        .take_while(|&(_, y)| dy > 0 && y < map.height())
+        .collect()
+}
+
+/// How wide the rendered map needs to be to fit the whole path: a whole number of copies
+fn rendered_width(map: &TreeMap, path: &[(usize, usize)]) -> usize {
+    let max_x = path.iter().map(|&(x, _)| x).max().unwrap_or(0);
+    (max_x / map.width() + 1) * map.width()
+}
+
+/// Draws the map, repeated to the right as far as the path goes, with the path for the slope
+/// drawn over it like the puzzle illustration: `O` where it hits a tree, `X` where it lands on
+/// an open square. The starting square is left unmarked.
+pub fn render_path(map: &TreeMap, dx: usize, dy: usize) -> String {
+    let path = path(map, dx, dy);
+    let width = rendered_width(map, &path);
+
+    let mut rows: Vec<Vec<char>> = (0..map.height())
+        .map(|y| {
+            (0..width)
+                .map(|x| if map.is_tree(x, y) { '#' } else { '.' })
+                .collect()
+        })
+        .collect();
+
+    for &(x, y) in &path {
+        rows[y][x] = if map.is_tree(x, y) { 'O' } else { 'X' };
+    }
+
+    rows.into_iter()
+        .map(|row| row.into_iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The same picture as `render_path`, as an SVG document, with the route drawn as a line
+pub fn render_path_svg(map: &TreeMap, dx: usize, dy: usize) -> String {
+    const CELL: usize = 10;
+
+    let path = path(map, dx, dy);
+    let width = rendered_width(map, &path);
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        width * CELL,
+        map.height() * CELL
+    );
+    out.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+
+    for y in 0..map.height() {
+        for x in (0..width).filter(|&x| map.is_tree(x, y)) {
+            out.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"forestgreen\"/>\n",
+                x * CELL,
+                y * CELL,
+                CELL,
+                CELL
+            ));
+        }
+    }
+
+    let center = |c: usize| c * CELL + CELL / 2;
+
+    let points = std::iter::once((0, 0))
+        .chain(path.iter().copied())
+        .map(|(x, y)| format!("{},{}", center(x), center(y)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    out.push_str(&format!(
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\"/>\n",
+        points
+    ));
+
+    for &(x, y) in &path {
+        let color = if map.is_tree(x, y) {
+            "red"
+        } else {
+            "steelblue"
+        };
+        out.push_str(&format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>\n",
+            center(x),
+            center(y),
+            CELL / 3,
+            color
+        ));
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
 fn run_3a_with_input(input: &str) -> usize {
-    let grid = parse(input);
+    let map = parse(input).unwrap();
 
-    count_collisions(&grid, 3, 1)
+    count_collisions(&map, 3, 1)
 }
 
 pub fn run_3a() -> usize {
@@ -44,13 +393,13 @@ pub fn run_3a() -> usize {
 }
 
 fn run_3b_with_input(input: &str) -> usize {
-    let grid = parse(input);
+    let map = parse(input).unwrap();
 
-    let a = count_collisions(&grid, 1, 1);
-    let b = count_collisions(&grid, 3, 1);
-    let c = count_collisions(&grid, 5, 1);
-    let d = count_collisions(&grid, 7, 1);
-    let e = count_collisions(&grid, 1, 2);
+    let a = count_collisions(&map, 1, 1);
+    let b = count_collisions(&map, 3, 1);
+    let c = count_collisions(&map, 5, 1);
+    let d = count_collisions(&map, 7, 1);
+    let e = count_collisions(&map, 1, 2);
 
     a * b * c * d * e
 }
@@ -59,6 +408,127 @@ pub fn run_3b() -> usize {
     run_3b_with_input(INPUT)
 }
 
+/// CLI entry point: `3rank <max_dx> <max_dy> [shown]`, listing the best and worst slopes
+/// for the puzzle input
+pub fn run_3rank(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 3rank <max_dx> <max_dy> [shown]";
+
+    let parse_arg = |i: usize| args.get(i).and_then(|s| s.parse::<usize>().ok());
+
+    let max_dx = parse_arg(0).ok_or(usage)?;
+    let max_dy = parse_arg(1).ok_or(usage)?;
+    let shown = match args.get(2) {
+        Some(_) => parse_arg(2).ok_or(usage)?,
+        None => 5,
+    };
+
+    if max_dy == 0 {
+        return Err("max_dy must be at least 1".to_string());
+    }
+
+    let map = parse(INPUT).unwrap();
+    let ranked = rank_slopes(&map, max_dx, max_dy);
+
+    let describe = |s: &SlopeScore| format!("  right {}, down {}: {} trees", s.dx, s.dy, s.trees);
+
+    let mut lines = vec!["Fewest trees:".to_string()];
+    lines.extend(ranked.iter().take(shown).map(describe));
+    lines.push("Most trees:".to_string());
+    lines.extend(ranked.iter().rev().take(shown).map(describe));
+
+    Ok(lines.join("\n"))
+}
+
+mod parse_route {
+    use super::{Segment, Slope};
+
+    use nom::{
+        character::complete::{char as exact_char, space1},
+        combinator::{eof, map, opt},
+        multi::separated_list1,
+        sequence::{preceded, tuple},
+        IResult,
+    };
+
+    use crate::parse_usize;
+
+    /// `right[/den],down[xsteps]`, e.g. `3/2,1x5`; no step count means "until the bottom"
+    fn parse_segment(input: &str) -> IResult<&str, (usize, usize, usize, usize)> {
+        map(
+            tuple((
+                parse_usize,
+                opt(preceded(exact_char('/'), parse_usize)),
+                exact_char(','),
+                parse_usize,
+                opt(preceded(exact_char('x'), parse_usize)),
+            )),
+            |(num, den, _, down, steps)| (num, den.unwrap_or(1), down, steps.unwrap_or(usize::MAX)),
+        )(input)
+    }
+
+    pub(super) fn parse(input: &str) -> Result<Vec<Segment>, String> {
+        let (_, raw) = map(
+            tuple((separated_list1(space1, parse_segment), eof)),
+            |(segments, _)| segments,
+        )(input)
+        .map_err(|e| format!("Could not parse route '{}': {:?}", input, e))?;
+
+        raw.into_iter()
+            .map(|(num, den, down, steps)| {
+                Ok(Segment {
+                    slope: Slope::new(num, den, down)?,
+                    steps,
+                })
+            })
+            .collect()
+    }
+}
+
+/// CLI entry point: `3route <segment>...`, where each segment looks like `3/2,1x5` (right
+/// 3/2 per row, for 5 steps); leave off the step count to keep going to the bottom
+pub fn run_3route(args: &[String]) -> Result<String, String> {
+    if args.is_empty() {
+        return Err("Usage: 3route <right[/den],down[xsteps]>...".to_string());
+    }
+
+    let segments = parse_route::parse(&args.join(" "))?;
+    let map = parse(INPUT).unwrap();
+    let report = ride_route(&map, &segments);
+
+    let mut lines: Vec<String> = segments
+        .iter()
+        .zip(report.segments.iter())
+        .map(|(s, r)| format!("{}: {} steps, {} trees", s, r.steps_taken, r.collisions))
+        .collect();
+    lines.push(format!("Total: {} trees", report.collisions));
+
+    Ok(lines.join("\n"))
+}
+
+/// CLI entry point: `3path <dx> <dy> [svg_file]`, drawing the route over the puzzle input and
+/// optionally also writing it out as an SVG
+pub fn run_3path(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 3path <dx> <dy> [svg_file]";
+
+    let parse_arg = |i: usize| args.get(i).and_then(|s| s.parse::<usize>().ok());
+
+    let dx = parse_arg(0).ok_or(usage)?;
+    let dy = parse_arg(1).ok_or(usage)?;
+
+    if dy == 0 {
+        return Err("dy must be at least 1".to_string());
+    }
+
+    let map = parse(INPUT).unwrap();
+
+    if let Some(svg_file) = args.get(2) {
+        std::fs::write(svg_file, render_path_svg(&map, dx, dy))
+            .map_err(|e| format!("Could not write {}: {}", svg_file, e))?;
+    }
+
+    Ok(render_path(&map, dx, dy))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,4 +554,157 @@ mod tests {
     fn sample_3b() {
         assert_eq!(run_3b_with_input(SAMPLE_3), 2 * 7 * 3 * 4 * 2)
     }
+
+    #[test]
+    fn rank_sample_slopes() {
+        let map = parse(SAMPLE_3).unwrap();
+        let width = map.width();
+
+        let ranked = rank_slopes(&map, 2 * width, 3);
+        assert_eq!(ranked.len(), (2 * width + 1) * 3);
+
+        for window in ranked.windows(2) {
+            assert!(window[0].trees <= window[1].trees);
+        }
+
+        let trees_for = |dx: usize, dy: usize| {
+            ranked
+                .iter()
+                .find(|s| s.dx == dx && s.dy == dy)
+                .map(|s| s.trees)
+                .unwrap()
+        };
+
+        assert_eq!(trees_for(1, 1), 2);
+        assert_eq!(trees_for(3, 1), 7);
+        assert_eq!(trees_for(5, 1), 3);
+        assert_eq!(trees_for(7, 1), 4);
+        assert_eq!(trees_for(1, 2), 2);
+
+        // wrapping all the way around is the same slope
+        assert_eq!(trees_for(3 + width, 1), 7);
+
+        for s in &ranked {
+            assert_eq!(s.trees, count_collisions(&map, s.dx, s.dy));
+        }
+    }
+    #[test]
+    fn tree_map_round_trip() {
+        let map = parse(SAMPLE_3).unwrap();
+        assert_eq!(map.height(), 11);
+        assert_eq!(map.width(), 11);
+        assert_eq!(map.to_string(), SAMPLE_3);
+
+        assert!(!map.is_tree(0, 0));
+        assert!(map.is_tree(2, 0));
+        assert!(map.is_tree(2 + 11 * 1000, 0));
+
+        // wider than a single word
+        let wide_row = "#".to_string() + &".".repeat(98) + "#";
+        let wide_input = format!("{}\n{}", wide_row, wide_row);
+        let wide = parse(&wide_input).unwrap();
+        assert_eq!(wide.width(), 100);
+        assert!(wide.is_tree(99, 1));
+        assert!(wide.is_tree(100, 1));
+        assert!(!wide.is_tree(64, 0));
+        assert_eq!(wide.to_string(), wide_input);
+    }
+
+    #[test]
+    fn render_sample_path() {
+        let map = parse(SAMPLE_3).unwrap();
+        let rendered = render_path(&map, 3, 1);
+        let rows: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(rows.len(), 11);
+        assert_eq!(rows[0], "..##.........##.........##.......");
+        assert_eq!(rows[1], "#..X#...#..#...#...#..#...#...#..");
+        assert_eq!(rows[2], ".#....O..#..#....#..#..#....#..#.");
+        assert_eq!(rows[10], ".#..#...#.#.#..#...#.#.#..#...O.#");
+
+        assert_eq!(rendered.matches('O').count(), 7);
+        assert_eq!(rendered.matches('X').count(), 3);
+
+        let svg = render_path_svg(&map, 3, 1);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("fill=\"red\"").count(), 7);
+    }
+
+    #[test]
+    fn rational_slopes() {
+        let map = parse(SAMPLE_3).unwrap();
+
+        for &(dx, dy) in &[(1, 1), (3, 1), (5, 1), (7, 1), (1, 2), (0, 3), (14, 1)] {
+            let slope = Slope::integer(dx, dy).unwrap();
+            assert_eq!(
+                count_collisions_on_slope(&map, slope),
+                count_collisions(&map, dx, dy)
+            );
+        }
+
+        // 6/2 is just 3
+        let slope = Slope::new(6, 2, 1).unwrap();
+        assert_eq!(slope, Slope::integer(3, 1).unwrap());
+
+        // half a square per row lands on columns 0, 1, 1, 2, 2, 3, 3, ...
+        let half = Slope::new(1, 2, 1).unwrap();
+        assert_eq!(count_collisions_on_slope(&map, half), 4);
+
+        assert!(Slope::new(1, 0, 1).is_err());
+        assert!(Slope::new(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn segmented_routes() {
+        let map = parse(SAMPLE_3).unwrap();
+        let three = Slope::integer(3, 1).unwrap();
+
+        let report = ride_route(
+            &map,
+            &[
+                Segment {
+                    slope: three,
+                    steps: 4,
+                },
+                Segment {
+                    slope: three,
+                    steps: 100,
+                },
+            ],
+        );
+        assert_eq!(report.collisions, 7);
+        assert_eq!(report.segments[0].steps_taken, 4);
+        assert_eq!(report.segments[1].steps_taken, 6);
+        assert_eq!(
+            report.segments[0].collisions + report.segments[1].collisions,
+            7
+        );
+
+        let parsed = parse_route::parse("3,1x4 1/2,1").unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].slope, three);
+        assert_eq!(parsed[0].steps, 4);
+        assert_eq!(parsed[1].steps, usize::MAX);
+        assert_eq!(parsed[0].to_string(), "3,1x4");
+        assert_eq!(parsed[1].to_string(), "1/2,1");
+
+        let mixed = ride_route(&map, &parsed);
+        assert_eq!(mixed.segments[0].steps_taken, 4);
+        assert_eq!(mixed.segments[1].steps_taken, 6);
+
+        assert!(parse_route::parse("3,0").is_err());
+        assert!(parse_route::parse("3").is_err());
+    }
+
+    #[test]
+    fn tree_map_validation() {
+        assert!(parse("..#\n.#").is_err());
+        assert!(parse("..#\n.x.").is_err());
+        assert!(parse("").is_err());
+    }
+    #[test]
+    #[should_panic(expected = "at least one row")]
+    fn flat_slope() {
+        count_collisions(&parse(SAMPLE_3).unwrap(), 3, 0);
+    }
 }
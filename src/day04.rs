@@ -1,180 +1,143 @@
 const INPUT: &str = include_str!("input/4.txt");
 
+pub mod reader;
+pub mod report;
+pub mod schema;
+pub mod valid;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::read_file;
+
+use reader::PassportReader;
+use report::{PassportReport, ValidationReport};
+use schema::{Document, Schema};
+use valid::ValidPassport;
+
 #[derive(Default, Debug)]
-struct ParsedPassport {
-    byr: Option<String>,
-    iyr: Option<String>,
-    eyr: Option<String>,
-    hgt: Option<String>,
-    hcl: Option<String>,
-    ecl: Option<String>,
-    pid: Option<String>,
-    cid: Option<String>,
+pub struct ParsedPassport {
+    /// Fields the schema knows about
+    fields: BTreeMap<String, String>,
+    /// Fields that aren't part of the schema (lenient mode only)
+    extras: BTreeMap<String, String>,
+    /// Anything odd that lenient mode skipped past
+    warnings: Vec<ParseWarning>,
+    /// 1-indexed line of the batch file this passport starts on
+    start_line: usize,
 }
 
-#[derive(Copy, Clone, Debug)]
-enum Height {
-    Cm(usize),
-    In(usize),
+/// How to treat fields that the schema doesn't define
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParseMode {
+    /// Any unknown, repeated or malformed field makes the whole passport an error
+    Strict,
+    /// Unknown fields are kept as extras, for the schema to report; repeats (the first value
+    /// wins) and malformed tokens are skipped and recorded as warnings
+    Lenient,
 }
 
-impl ParsedPassport {
-    fn is_valid_a(&self) -> bool {
-        self.byr.is_some()
-            && self.iyr.is_some()
-            && self.eyr.is_some()
-            && self.hgt.is_some()
-            && self.hcl.is_some()
-            && self.ecl.is_some()
-            && self.pid.is_some()
-    }
+/// Something wrong with a single `key:value` token
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FieldIssue {
+    Unknown { field: String },
+    Duplicate { field: String },
+    Malformed { token: String },
+}
 
-    fn is_valid_b(&self) -> Result<(), String> {
-        self.byr
-            .as_ref()
-            .map(|byr| parse::match_year(byr))
-            .flatten()
-            .filter(|&byr| 1920 <= byr && byr <= 2002)
-            .ok_or_else(|| format!("Invalid byr: {:?}", self.byr))?;
-
-        self.iyr
-            .as_ref()
-            .map(|byr| parse::match_year(byr))
-            .flatten()
-            .filter(|&byr| 2010 <= byr && byr <= 2020)
-            .ok_or_else(|| format!("Invalid iyr: {:?}", self.iyr))?;
-
-        self.eyr
-            .as_ref()
-            .map(|byr| parse::match_year(byr))
-            .flatten()
-            .filter(|&byr| 2020 <= byr && byr <= 2030)
-            .ok_or_else(|| format!("Invalid eyr: {:?}", self.eyr))?;
-
-        self.hgt
-            .as_ref()
-            .map(|hgt| parse::match_hgt(hgt))
-            .flatten()
-            .filter(|&hgt| match hgt {
-                Height::Cm(cm) => cm >= 150 && cm <= 193,
-                Height::In(inches) => inches >= 59 && inches <= 76,
-            })
-            .ok_or_else(|| format!("Invalid hgt: {:?}", self.hgt))?;
-
-        self.hcl
-            .as_ref()
-            .map(|hcl| parse::match_hair_color(hcl))
-            .flatten()
-            .ok_or_else(|| format!("Invalid hcl: {:?}", self.hcl))?;
-
-        self.ecl
-            .as_ref()
-            .map(|ecl| parse::match_ecl(ecl))
-            .flatten()
-            .ok_or_else(|| format!("Invalid ecl: {:?}", self.ecl))?;
-
-        self.pid
-            .as_ref()
-            .map(|pid| parse::match_pid(pid))
-            .flatten()
-            .ok_or_else(|| format!("Invalid pid: {:?}", self.pid))?;
-
-        Ok(())
+impl fmt::Display for FieldIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldIssue::Unknown { field } => write!(f, "unknown field {}", field),
+            FieldIssue::Duplicate { field } => write!(f, "duplicate {}", field),
+            FieldIssue::Malformed { token } => write!(f, "malformed token {:?}", token),
+        }
     }
 }
 
-mod parse {
-    use super::{Height, ParsedPassport};
-
-    use std::collections::HashMap;
+/// A field lenient mode skipped past
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseWarning {
+    /// 1-indexed line the token is on
+    pub line: usize,
+    pub issue: FieldIssue,
+}
 
-    use nom::{
-        branch::alt,
-        bytes::complete::tag,
-        character::complete::{char as exact_char, digit1, hex_digit1, none_of},
-        combinator::{eof, map, verify},
-        multi::fold_many1,
-        sequence::tuple,
-        IResult,
-    };
+/// Why strict mode rejected a passport
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    /// 1-indexed line the passport starts on
+    pub start_line: usize,
+    /// 1-indexed line the offending token is on
+    pub line: usize,
+    pub issue: FieldIssue,
+}
 
-    fn res_to_opt<O, E>(r: Result<O, E>) -> Option<()> {
-        match r {
-            Ok(_) => Some(()),
-            Err(_) => None,
-        }
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.issue)
     }
+}
 
-    pub(super) fn match_hgt(input: &str) -> Option<Height> {
-        let res: IResult<&str, Height> = map(
-            tuple((
-                alt((
-                    map(tuple((digit1, tag("cm"))), |(d, _): (&str, _)| {
-                        let cm = d.parse::<usize>().unwrap();
-                        Height::Cm(cm)
-                    }),
-                    map(tuple((digit1, tag("in"))), |(d, _): (&str, _)| {
-                        let inches = d.parse::<usize>().unwrap();
-                        Height::In(inches)
-                    }),
-                )),
-                eof,
-            )),
-            |(b, _)| b,
-        )(input);
+impl Document for ParsedPassport {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .get(name)
+            .or_else(|| self.extras.get(name))
+            .map(|v| v.as_str())
+    }
 
-        res.ok().map(|(_, h)| h)
+    fn field_names(&self) -> Vec<&str> {
+        self.fields
+            .keys()
+            .chain(self.extras.keys())
+            .map(|k| k.as_str())
+            .collect()
     }
+}
 
-    pub fn match_pid(input: &str) -> Option<()> {
-        let res: IResult<&str, ()> =
-            map(tuple((verify(digit1, |s: &str| s.len() == 9), eof)), |_| ())(input);
+impl ParsedPassport {
+    pub fn start_line(&self) -> usize {
+        self.start_line
+    }
 
-        res_to_opt(res)
+    pub fn fields(&self) -> &BTreeMap<String, String> {
+        &self.fields
     }
 
-    pub fn match_year(input: &str) -> Option<usize> {
-        let res: IResult<&str, usize> = map(
-            tuple((verify(digit1, |v: &str| v.len() == 4), eof)),
-            |(digits, _): (&str, _)| digits.parse::<usize>().unwrap(),
-        )(input);
+    pub fn extras(&self) -> &BTreeMap<String, String> {
+        &self.extras
+    }
 
-        match res {
-            Ok(("", year)) => Some(year),
-            _ => None,
-        }
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
     }
 
-    pub fn match_hair_color(input: &str) -> Option<()> {
-        let res: IResult<&str, ()> = map(
-            tuple((
-                exact_char('#'),
-                verify(hex_digit1, |s: &str| s.len() == 6),
-                eof,
-            )),
-            |_| (),
-        )(input);
+    fn is_valid_a(&self, schema: &Schema) -> bool {
+        schema.has_required_fields(self)
+    }
 
-        res_to_opt(res)
+    fn is_valid_b(&self, schema: &Schema) -> Result<(), String> {
+        schema.validate(self)
     }
+}
 
-    pub fn match_ecl(input: &str) -> Option<()> {
-        //amb blu brn gry grn hzl oth
-        let res: IResult<&str, ()> = map(
-            alt((
-                tag("amb"),
-                tag("blu"),
-                tag("brn"),
-                tag("gry"),
-                tag("grn"),
-                tag("hzl"),
-                tag("oth"),
-            )),
-            |_| (),
-        )(input);
+mod parse {
+    use super::{
+        FieldIssue, ParseError, ParseMode, ParseWarning, ParsedPassport, PassportReader, Schema,
+    };
 
-        res_to_opt(res)
-    }
+    use std::collections::btree_map::Entry;
+
+    use nom::{
+        character::complete::{char as exact_char, none_of},
+        combinator::{eof, map},
+        multi::fold_many1,
+        sequence::tuple,
+        IResult,
+    };
 
     fn parse_field(input: &str) -> IResult<&str, (String, String)> {
         map(
@@ -194,54 +157,91 @@ mod parse {
         )(input)
     }
 
-    fn parse_passport(input: &str) -> IResult<&str, ParsedPassport> {
-        let mut fields: HashMap<String, String> = HashMap::new();
+    /// Parses one passport's worth of text, which starts on `start_line` of the batch. `known`
+    /// is every field the schema defines.
+    pub(super) fn parse_passport(
+        input: &str,
+        start_line: usize,
+        mode: ParseMode,
+        known: &[String],
+    ) -> Result<ParsedPassport, ParseError> {
+        let mut passport = ParsedPassport {
+            start_line,
+            ..ParsedPassport::default()
+        };
+
+        let tokens = input.lines().enumerate().flat_map(|(offset, line)| {
+            line.split_whitespace()
+                .map(move |token| (start_line + offset, token))
+        });
+
+        for (line, field_decl) in tokens {
+            let issue = match parse_field(field_decl) {
+                Err(_) => Some(FieldIssue::Malformed {
+                    token: field_decl.to_string(),
+                }),
+                Ok((_, (k, v))) => {
+                    let slot = if known.contains(&k) {
+                        Some(&mut passport.fields)
+                    } else if mode == ParseMode::Lenient {
+                        Some(&mut passport.extras)
+                    } else {
+                        None
+                    };
+
+                    match slot.map(|fields| fields.entry(k.clone())) {
+                        None => Some(FieldIssue::Unknown { field: k }),
+                        Some(Entry::Occupied(_)) => Some(FieldIssue::Duplicate { field: k }),
+                        Some(Entry::Vacant(e)) => {
+                            e.insert(v);
+                            None
+                        }
+                    }
+                }
+            };
 
-        for field_decl in input.split_whitespace() {
-            let (_, (k, v)) = parse_field(field_decl)?;
-            if fields.insert(k, v).is_some() {
-                panic!("Field should not be double assigned");
+            match (issue, mode) {
+                (None, _) => {}
+                (Some(issue), ParseMode::Strict) => {
+                    return Err(ParseError {
+                        start_line,
+                        line,
+                        issue,
+                    });
+                }
+                (Some(issue), ParseMode::Lenient) => {
+                    passport.warnings.push(ParseWarning { line, issue });
+                }
             }
         }
 
-        let mut passport = ParsedPassport::default();
-
-        for (k, v) in fields {
-            let field_ref = match k.as_str() {
-                "byr" => &mut passport.byr,
-                "iyr" => &mut passport.iyr,
-                "eyr" => &mut passport.eyr,
-                "hgt" => &mut passport.hgt,
-                "hcl" => &mut passport.hcl,
-                "ecl" => &mut passport.ecl,
-                "pid" => &mut passport.pid,
-                "cid" => &mut passport.cid,
-                other => {
-                    panic!("Unrecognized field {}", other);
-                }
-            };
-            *field_ref = Some(v);
-        }
+        Ok(passport)
+    }
 
-        Ok(("", passport))
+    pub(super) fn parse_batch(
+        input: &str,
+        mode: ParseMode,
+        schema: &Schema,
+    ) -> Vec<Result<ParsedPassport, ParseError>> {
+        PassportReader::new(input.as_bytes(), mode, schema)
+            .map(|p| p.expect("Reading from memory can't fail"))
+            .collect()
     }
 
+    /// Lenient parsing against the puzzle's passport fields
     pub(super) fn parse_input(input: &str) -> Vec<ParsedPassport> {
-        let mut out = Vec::new();
-
-        for passport in input.split("\n\n") {
-            let (_, passport) = parse_passport(passport).unwrap();
-            out.push(passport);
-        }
-
-        out
+        parse_batch(input, ParseMode::Lenient, &Schema::default_passport())
+            .into_iter()
+            .map(|p| p.expect("Lenient parsing never fails"))
+            .collect()
     }
 }
 
 fn run_4a_with_input(input: &str) -> usize {
     let passports = parse::parse_input(input);
+    let schema = Schema::default_passport();
 
-    passports.iter().filter(|p| p.is_valid_a()).count()
+    passports.iter().filter(|p| p.is_valid_a(&schema)).count()
 }
 
 pub fn run_4a() -> usize {
@@ -250,14 +250,111 @@ pub fn run_4a() -> usize {
 
 fn run_4b_with_input(input: &str) -> usize {
     let passports = parse::parse_input(input);
+    let schema = Schema::default_passport();
 
-    passports.iter().filter(|p| p.is_valid_b().is_ok()).count()
+    passports
+        .iter()
+        .filter(|p| p.is_valid_b(&schema).is_ok())
+        .count()
 }
 
 pub fn run_4b() -> usize {
     run_4b_with_input(INPUT)
 }
 
+/// CLI entry point: `4check [--schema <file>] [--input <file>] [--report text|json]
+/// [--mode strict|lenient] [--normalize batch|csv]`, checking the passports against the schema
+/// (by default, the puzzle rules against the puzzle input, parsed leniently). With
+/// `--normalize`, writes out the valid passports instead.
+pub fn run_4check(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 4check [--schema <file>] [--input <file>] [--report text|json] \
+                 [--mode strict|lenient] [--normalize batch|csv]";
+
+    let mut schema_file = None;
+    let mut input_file = None;
+    let mut report_format = None;
+    let mut mode = None;
+    let mut normalize = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let target = match arg.as_str() {
+            "--schema" => &mut schema_file,
+            "--input" => &mut input_file,
+            "--report" => &mut report_format,
+            "--mode" => &mut mode,
+            "--normalize" => &mut normalize,
+            _ => return Err(usage.to_string()),
+        };
+        *target = Some(args.next().ok_or(usage)?);
+    }
+
+    let schema = match schema_file {
+        Some(path) => Schema::parse(&read_file(path)?)?,
+        None => Schema::default_passport(),
+    };
+
+    let mode = match mode.map(|s| s.as_str()) {
+        None | Some("lenient") => ParseMode::Lenient,
+        Some("strict") => ParseMode::Strict,
+        Some(other) => return Err(format!("Unrecognized parse mode {}; {}", other, usage)),
+    };
+
+    // Check the output formats before reading anything, which could take a while
+    match (
+        normalize.map(|s| s.as_str()),
+        report_format.map(|s| s.as_str()),
+    ) {
+        (None, None) | (None, Some("text")) | (None, Some("json")) => {}
+        (Some("batch"), None) | (Some("csv"), None) => {}
+        _ => return Err(usage.to_string()),
+    }
+
+    // Input files are streamed, so only the reports (or valid passports) are kept in memory
+    let input: Box<dyn BufRead> = match input_file {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).map_err(|e| format!("Could not read {}: {}", path, e))?,
+        )),
+        None => Box::new(INPUT.as_bytes()),
+    };
+
+    let mut reports = Vec::new();
+    let mut valid = Vec::new();
+
+    for (index, passport) in PassportReader::new(input, mode, &schema).enumerate() {
+        let passport = passport.map_err(|e| format!("Could not read input: {}", e))?;
+
+        if normalize.is_some() {
+            if let Some(p) = passport
+                .as_ref()
+                .ok()
+                .and_then(|p| ValidPassport::from_document(p, &schema).ok())
+            {
+                valid.push(p);
+            }
+        } else {
+            reports.push(PassportReport::new(index, &passport, &schema));
+        }
+    }
+
+    let report = ValidationReport { passports: reports };
+
+    match (
+        normalize.map(|s| s.as_str()),
+        report_format.map(|s| s.as_str()),
+    ) {
+        (Some("batch"), _) => Ok(valid::to_batch(&valid)),
+        (Some("csv"), _) => Ok(valid::to_csv(&valid)),
+        (_, Some("text")) => Ok(report.to_text()),
+        (_, Some("json")) => Ok(report.to_json()),
+        _ => Ok(format!(
+            "{} of {} passports valid",
+            report.valid_count(),
+            report.passports.len()
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -310,15 +407,17 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
 
     #[test]
     fn sample_4b() {
+        let schema = Schema::default_passport();
+
         let bad = parse::parse_input(ALL_BAD);
         for b in &bad {
-            let r = b.is_valid_b();
+            let r = b.is_valid_b(&schema);
             assert!(r.is_err(), "Should be invalid: {:?}", b);
         }
 
         let good = parse::parse_input(ALL_GOOD);
         for g in &good {
-            let r = g.is_valid_b();
+            let r = g.is_valid_b(&schema);
             assert!(
                 r.is_ok(),
                 "Should be valid: {:?}; rejection: {:?}",
@@ -330,4 +429,259 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
         assert_eq!(run_4b_with_input(ALL_BAD), 0);
         assert_eq!(run_4b_with_input(ALL_GOOD), 4);
     }
+
+    const MESSY: &str = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+hcl:#623a2f
+
+
+eyr:2029 ecl:blu cid:129 byr:1989 pid:896056539
+iyr:2014 pid:896056539 hcl:#a97842 hgt:165 wat:yes
+
+hcl:#888785
+hgt:164cm byr:2003 iyr:2015
+ecl:hzl";
+
+    #[test]
+    fn full_report() {
+        let schema = Schema::default_passport();
+        let passports = parse::parse_batch(MESSY, ParseMode::Lenient, &schema);
+        let report = ValidationReport::build(&passports, &schema);
+
+        assert_eq!(report.valid_count(), 1);
+        assert_eq!(
+            report
+                .passports
+                .iter()
+                .map(|p| p.start_line)
+                .collect::<Vec<_>>(),
+            vec![1, 5, 8]
+        );
+
+        let kinds = |i: usize| {
+            report.passports[i]
+                .problems
+                .iter()
+                .map(|p| (p.kind(), p.field().unwrap().to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(kinds(0).is_empty());
+        assert_eq!(
+            kinds(1),
+            vec![
+                ("malformed", "hgt".to_string()),
+                ("unknown", "wat".to_string()),
+            ]
+        );
+        assert_eq!(
+            report.passports[1].warnings,
+            vec![ParseWarning {
+                line: 6,
+                issue: FieldIssue::Duplicate {
+                    field: "pid".to_string()
+                }
+            }]
+        );
+        assert_eq!(
+            kinds(2),
+            vec![
+                ("out_of_range", "byr".to_string()),
+                ("missing", "eyr".to_string()),
+                ("missing", "pid".to_string()),
+            ]
+        );
+
+        let text = report.to_text();
+        assert!(text.contains("passport 1 (line 5): malformed hgt: \"165\"; unknown field wat"));
+        assert!(text.contains("passport 1 (line 5): warning: duplicate pid on line 6"));
+        assert!(text.ends_with("1 of 3 passports valid"));
+
+        let json = report.to_json();
+        assert!(json.starts_with("{\"valid\":1,\"total\":3,"));
+        assert!(json.contains(
+            "{\"index\":2,\"line\":8,\"valid\":false,\"problems\":[{\"kind\":\"out_of_range\",\"field\":\"byr\",\"value\":\"2003\"}"
+        ));
+    }
+
+    #[test]
+    fn strict_and_lenient_parsing() {
+        let batch = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm
+
+ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm nonsense
+
+ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm
+hat:pointy";
+
+        let schema = Schema::default_passport();
+        let strict = parse::parse_batch(batch, ParseMode::Strict, &schema);
+        assert_eq!(strict.len(), 3);
+        assert!(strict[0].is_ok());
+        assert_eq!(
+            strict[1].as_ref().unwrap_err(),
+            &ParseError {
+                start_line: 4,
+                line: 5,
+                issue: FieldIssue::Malformed {
+                    token: "nonsense".to_string()
+                }
+            }
+        );
+        assert_eq!(
+            strict[2].as_ref().unwrap_err().issue,
+            FieldIssue::Unknown {
+                field: "hat".to_string()
+            }
+        );
+
+        let lenient = parse::parse_batch(batch, ParseMode::Lenient, &schema);
+        let passports: Vec<&ParsedPassport> = lenient.iter().map(|p| p.as_ref().unwrap()).collect();
+        assert_eq!(passports[1].warnings().len(), 1);
+        assert_eq!(passports[2].extras().get("hat").unwrap(), "pointy");
+        assert_eq!(passports[2].field("hat"), Some("pointy"));
+
+        // Both modes feed the report rather than crashing
+        assert_eq!(ValidationReport::build(&strict, &schema).valid_count(), 1);
+        assert_eq!(ValidationReport::build(&lenient, &schema).valid_count(), 2);
+
+        // Unknown means unknown to the schema in use, so one which expects these fields is
+        // satisfied in either mode
+        let badges = Schema::parse("id required range 0..999\nhat required any").unwrap();
+        let batch = "id:007 hat:pointy\n\nhat:flat id:123";
+        for mode in [ParseMode::Strict, ParseMode::Lenient].iter() {
+            let parsed = parse::parse_batch(batch, *mode, &badges);
+            assert_eq!(ValidationReport::build(&parsed, &badges).valid_count(), 2);
+        }
+    }
+
+    #[test]
+    fn typed_passports() {
+        let schema = Schema::default_passport();
+        let passports: Vec<ValidPassport> = parse::parse_input(ALL_GOOD)
+            .iter()
+            .map(|p| ValidPassport::from_document(p, &schema).unwrap())
+            .collect();
+
+        assert_eq!(
+            passports[0],
+            ValidPassport {
+                byr: 1980,
+                iyr: 2012,
+                eyr: 2030,
+                hgt: valid::Height::In(74),
+                hcl: valid::Rgb {
+                    r: 0x62,
+                    g: 0x3a,
+                    b: 0x2f
+                },
+                ecl: valid::EyeColor::Green,
+                pid: "087499704".to_string(),
+                cid: None,
+            }
+        );
+        assert!((passports[0].hgt.to_cm() - 187.96).abs() < 1e-9);
+        assert_eq!(passports[1].cid, Some("129".to_string()));
+
+        for bad in parse::parse_input(ALL_BAD) {
+            assert!(ValidPassport::from_document(&bad, &schema).is_err());
+        }
+
+        // A looser schema lets through values the types can't hold
+        let loose = Schema::parse("ecl required any").unwrap();
+        let odd = parse::parse_input("ecl:zzz");
+        assert_eq!(
+            ValidPassport::from_document(&odd[0], &loose).unwrap_err()[0],
+            schema::Problem::Missing {
+                field: "byr".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn normalized_output() {
+        let schema = Schema::default_passport();
+        let passports: Vec<ValidPassport> = parse::parse_input(
+            "hcl:#ABCDEF pid:087499704 hgt:74in ecl:grn
+iyr:2012 eyr:2030 byr:1980
+
+eyr:2029 ecl:blu cid:129 byr:1989
+iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm",
+        )
+        .iter()
+        .map(|p| ValidPassport::from_document(p, &schema).unwrap())
+        .collect();
+
+        let batch = valid::to_batch(&passports);
+        assert_eq!(
+            batch,
+            "byr:1980 iyr:2012 eyr:2030 hgt:74in hcl:#abcdef ecl:grn pid:087499704
+
+byr:1989 iyr:2014 eyr:2029 hgt:165cm hcl:#a97842 ecl:blu pid:896056539 cid:129"
+        );
+
+        // Normalized output is itself a valid batch, and normalizes to itself
+        let reparsed: Vec<ValidPassport> = parse::parse_input(&batch)
+            .iter()
+            .map(|p| ValidPassport::from_document(p, &schema).unwrap())
+            .collect();
+        assert_eq!(reparsed, passports);
+
+        assert_eq!(
+            valid::to_csv(&passports),
+            "byr,iyr,eyr,hgt,hgt_cm,hcl,ecl,pid,cid
+1980,2012,2030,74in,187.96,#abcdef,grn,087499704,
+1989,2014,2029,165cm,165.00,#a97842,blu,896056539,129"
+        );
+
+        // A looser schema can let through ids which need quoting
+        let mut odd = passports[0].clone();
+        odd.pid = "12,\"3\"".to_string();
+        assert!(valid::to_csv(&[odd]).ends_with(",grn,\"12,\"\"3\"\"\","));
+    }
+
+    #[test]
+    fn streaming_reader() {
+        let batch = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd\r
+byr:1937 iyr:2017 cid:147 hgt:183cm\r
+\r
+   \r
+\r
+iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884\r
+hcl:#cfa07d byr:1929 bad\r
+\r
+";
+
+        let passports: Vec<Result<ParsedPassport, ParseError>> = PassportReader::new(
+            batch.as_bytes(),
+            ParseMode::Strict,
+            &Schema::default_passport(),
+        )
+        .map(|p| p.unwrap())
+        .collect();
+
+        assert_eq!(passports.len(), 2);
+
+        let first = passports[0].as_ref().unwrap();
+        assert_eq!(first.start_line(), 1);
+        assert_eq!(first.field("hgt"), Some("183cm"));
+        assert_eq!(first.field("hcl"), Some("#fffffd"));
+        assert!(first.is_valid_b(&Schema::default_passport()).is_ok());
+
+        assert_eq!(
+            passports[1].as_ref().unwrap_err(),
+            &ParseError {
+                start_line: 6,
+                line: 7,
+                issue: FieldIssue::Malformed {
+                    token: "bad".to_string()
+                }
+            }
+        );
+
+        // CRLF line endings give the same answers
+        let crlf = SAMPLE.replace('\n', "\r\n");
+        assert_eq!(run_4a_with_input(&crlf), run_4a_with_input(SAMPLE));
+    }
 }
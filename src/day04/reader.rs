@@ -0,0 +1,91 @@
+use std::io::{self, BufRead};
+
+use super::parse::parse_passport;
+use super::{ParseError, ParseMode, ParsedPassport, Schema};
+
+/// Reads a batch file one passport at a time, so the whole file never has to be in memory.
+/// Handles `\r\n` line endings, and treats any run of blank (or whitespace-only) lines as a
+/// single separator.
+pub struct PassportReader<R: BufRead> {
+    reader: R,
+    mode: ParseMode,
+    /// The fields the schema defines, so strict mode can reject the rest
+    known: Vec<String>,
+    /// 1-indexed number of the last line read
+    line: usize,
+    buffer: String,
+    done: bool,
+}
+
+impl<R: BufRead> PassportReader<R> {
+    pub fn new(reader: R, mode: ParseMode, schema: &Schema) -> PassportReader<R> {
+        PassportReader {
+            reader,
+            mode,
+            known: schema.fields().iter().map(|f| f.name.clone()).collect(),
+            line: 0,
+            buffer: String::new(),
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PassportReader<R> {
+    /// The outer error is for failing to read at all; the inner one is strict mode rejecting
+    /// a passport, after which reading can carry on
+    type Item = io::Result<Result<ParsedPassport, ParseError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut chunk = String::new();
+        let mut start_line = 0;
+
+        loop {
+            self.buffer.clear();
+            let read = match self.reader.read_line(&mut self.buffer) {
+                Ok(read) => read,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+
+            if read == 0 {
+                self.done = true;
+                break;
+            }
+
+            self.line += 1;
+            let line = self.buffer.trim_end_matches(&['\n', '\r'][..]);
+
+            if line.trim().is_empty() {
+                if chunk.is_empty() {
+                    continue;
+                } else {
+                    break;
+                }
+            }
+
+            if chunk.is_empty() {
+                start_line = self.line;
+            } else {
+                chunk.push('\n');
+            }
+            chunk.push_str(line);
+        }
+
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(Ok(parse_passport(
+                &chunk,
+                start_line,
+                self.mode,
+                &self.known,
+            )))
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use super::schema::{Problem, Schema};
+use super::{FieldIssue, ParseError, ParseWarning, ParsedPassport};
+
+use crate::json_string;
+
+/// Everything wrong with one passport in a batch
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PassportReport {
+    /// 0-indexed position of the passport in the batch
+    pub index: usize,
+    /// 1-indexed line the passport starts on
+    pub start_line: usize,
+    /// Anything which makes the passport invalid
+    pub problems: Vec<Problem>,
+    /// Things lenient parsing skipped past, which don't affect validity
+    pub warnings: Vec<ParseWarning>,
+}
+
+impl PassportReport {
+    /// Checks one passport from a batch; if strict parsing rejected it, the parse error is its
+    /// only problem
+    pub fn new(
+        index: usize,
+        passport: &Result<ParsedPassport, ParseError>,
+        schema: &Schema,
+    ) -> PassportReport {
+        match passport {
+            Ok(passport) => PassportReport {
+                index,
+                start_line: passport.start_line(),
+                problems: schema.problems(passport),
+                warnings: passport.warnings().to_vec(),
+            },
+            Err(e) => PassportReport {
+                index,
+                start_line: e.start_line,
+                problems: vec![issue_to_problem(&e.issue)],
+                warnings: Vec::new(),
+            },
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ValidationReport {
+    pub passports: Vec<PassportReport>,
+}
+
+fn issue_to_problem(issue: &FieldIssue) -> Problem {
+    match issue {
+        FieldIssue::Unknown { field } => Problem::Unknown {
+            field: field.clone(),
+        },
+        FieldIssue::Duplicate { field } => Problem::Duplicate {
+            field: field.clone(),
+        },
+        FieldIssue::Malformed { token } => Problem::BadToken {
+            token: token.clone(),
+        },
+    }
+}
+
+fn problem_to_json(problem: &Problem) -> String {
+    let mut fields = vec![format!("\"kind\":{}", json_string(problem.kind()))];
+    if let Some(field) = problem.field() {
+        fields.push(format!("\"field\":{}", json_string(field)));
+    }
+    if let Some(value) = problem.value() {
+        fields.push(format!("\"value\":{}", json_string(value)));
+    }
+    format!("{{{}}}", fields.join(","))
+}
+
+impl ValidationReport {
+    pub fn build(
+        passports: &[Result<ParsedPassport, ParseError>],
+        schema: &Schema,
+    ) -> ValidationReport {
+        let passports = passports
+            .iter()
+            .enumerate()
+            .map(|(index, passport)| PassportReport::new(index, passport, schema))
+            .collect();
+
+        ValidationReport { passports }
+    }
+
+    pub fn valid_count(&self) -> usize {
+        self.passports.iter().filter(|p| p.is_valid()).count()
+    }
+
+    /// One line per passport with problems or warnings, then a summary line
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+
+        for p in &self.passports {
+            if !p.is_valid() {
+                let problems: Vec<String> = p.problems.iter().map(|pr| pr.to_string()).collect();
+                lines.push(format!(
+                    "passport {} (line {}): {}",
+                    p.index,
+                    p.start_line,
+                    problems.join("; ")
+                ));
+            }
+            for w in &p.warnings {
+                lines.push(format!(
+                    "passport {} (line {}): warning: {} on line {}",
+                    p.index, p.start_line, w.issue, w.line
+                ));
+            }
+        }
+
+        lines.push(format!(
+            "{} of {} passports valid",
+            self.valid_count(),
+            self.passports.len()
+        ));
+
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let passports: Vec<String> = self
+            .passports
+            .iter()
+            .map(|p| {
+                let problems: Vec<String> = p.problems.iter().map(problem_to_json).collect();
+                let warnings: Vec<String> = p
+                    .warnings
+                    .iter()
+                    .map(|w| {
+                        format!(
+                            "{{\"line\":{},\"problem\":{}}}",
+                            w.line,
+                            problem_to_json(&issue_to_problem(&w.issue))
+                        )
+                    })
+                    .collect();
+
+                format!(
+                    "{{\"index\":{},\"line\":{},\"valid\":{},\"problems\":[{}],\"warnings\":[{}]}}",
+                    p.index,
+                    p.start_line,
+                    p.is_valid(),
+                    problems.join(","),
+                    warnings.join(",")
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"valid\":{},\"total\":{},\"passports\":[{}]}}",
+            self.valid_count(),
+            self.passports.len(),
+            passports.join(",")
+        )
+    }
+}
@@ -0,0 +1,488 @@
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+
+const DEFAULT_PASSPORT_SCHEMA: &str = include_str!("passport_schema.txt");
+
+/// Anything a schema can look fields up in
+pub trait Document {
+    fn field(&self, name: &str) -> Option<&str>;
+
+    /// Every field the document has a value for
+    fn field_names(&self) -> Vec<&str>;
+}
+
+#[derive(Debug, Clone)]
+pub enum FieldType {
+    /// Any value at all
+    Any,
+    /// Exactly four digits, between `min` and `max` (inclusive)
+    Year { min: u32, max: u32 },
+    /// Any integer between `min` and `max` (inclusive)
+    Range { min: i64, max: i64 },
+    /// The whole value matches the pattern
+    Regex(Regex),
+    /// One of a fixed list of values
+    Enum(Vec<String>),
+    /// A number directly followed by one of the units, within that unit's range
+    Measurement(Vec<UnitRange>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnitRange {
+    pub unit: String,
+    pub min: i64,
+    pub max: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldRule {
+    pub name: String,
+    pub required: bool,
+    pub field_type: FieldType,
+}
+
+/// Why a value doesn't fit its field type
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ValueError {
+    /// Not even the right shape, e.g. letters in a year or an unknown unit
+    Malformed,
+    /// The right shape, but not one of the allowed values
+    OutOfRange,
+}
+
+/// One thing wrong with a document
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Problem {
+    Missing {
+        field: String,
+    },
+    Malformed {
+        field: String,
+        value: String,
+    },
+    OutOfRange {
+        field: String,
+        value: String,
+    },
+    Duplicate {
+        field: String,
+    },
+    Unknown {
+        field: String,
+    },
+    /// A token that isn't even `key:value`
+    BadToken {
+        token: String,
+    },
+}
+
+impl Problem {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Problem::Missing { .. } => "missing",
+            Problem::Malformed { .. } => "malformed",
+            Problem::OutOfRange { .. } => "out_of_range",
+            Problem::Duplicate { .. } => "duplicate",
+            Problem::Unknown { .. } => "unknown",
+            Problem::BadToken { .. } => "bad_token",
+        }
+    }
+
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Problem::Missing { field }
+            | Problem::Malformed { field, .. }
+            | Problem::OutOfRange { field, .. }
+            | Problem::Duplicate { field }
+            | Problem::Unknown { field } => Some(field),
+            Problem::BadToken { .. } => None,
+        }
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            Problem::Malformed { value, .. } | Problem::OutOfRange { value, .. } => Some(value),
+            Problem::BadToken { token } => Some(token),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing { field } => write!(f, "missing {}", field),
+            Problem::Malformed { field, value } => write!(f, "malformed {}: {:?}", field, value),
+            Problem::OutOfRange { field, value } => {
+                write!(f, "{} out of range: {:?}", field, value)
+            }
+            Problem::Duplicate { field } => write!(f, "duplicate {}", field),
+            Problem::Unknown { field } => write!(f, "unknown field {}", field),
+            Problem::BadToken { token } => write!(f, "malformed token {:?}", token),
+        }
+    }
+}
+
+/// A set of field rules for one kind of document
+#[derive(Debug, Clone)]
+pub struct Schema {
+    fields: Vec<FieldRule>,
+}
+
+fn in_range<T: PartialOrd>(value: T, min: T, max: T) -> Result<(), ValueError> {
+    if min <= value && value <= max {
+        Ok(())
+    } else {
+        Err(ValueError::OutOfRange)
+    }
+}
+
+impl FieldType {
+    pub fn check(&self, value: &str) -> Result<(), ValueError> {
+        match self {
+            FieldType::Any => Ok(()),
+            FieldType::Year { min, max } => {
+                if value.len() != 4 || !value.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(ValueError::Malformed);
+                }
+                let year = value.parse::<u32>().map_err(|_| ValueError::Malformed)?;
+                in_range(year, *min, *max)
+            }
+            FieldType::Range { min, max } => {
+                let n = value.parse::<i64>().map_err(|_| ValueError::Malformed)?;
+                in_range(n, *min, *max)
+            }
+            FieldType::Regex(re) => {
+                if re.is_match(value) {
+                    Ok(())
+                } else {
+                    Err(ValueError::Malformed)
+                }
+            }
+            FieldType::Enum(options) => {
+                if options.iter().any(|o| o == value) {
+                    Ok(())
+                } else {
+                    Err(ValueError::OutOfRange)
+                }
+            }
+            FieldType::Measurement(units) => {
+                let digits = value.chars().take_while(|c| c.is_ascii_digit()).count();
+                let (amount, unit) = value.split_at(digits);
+
+                let amount = amount.parse::<i64>().map_err(|_| ValueError::Malformed)?;
+                let range = units
+                    .iter()
+                    .find(|u| u.unit == unit)
+                    .ok_or(ValueError::Malformed)?;
+                in_range(amount, range.min, range.max)
+            }
+        }
+    }
+
+    pub fn accepts(&self, value: &str) -> bool {
+        self.check(value).is_ok()
+    }
+}
+
+fn parse_range<T: FromStr>(text: &str) -> Result<(T, T), String> {
+    let mut parts = text.splitn(2, "..");
+    let min = parts.next().and_then(|s| s.parse::<T>().ok());
+    let max = parts.next().and_then(|s| s.parse::<T>().ok());
+
+    match (min, max) {
+        (Some(min), Some(max)) => Ok((min, max)),
+        _ => Err(format!("Expected a range like 10..20, got '{}'", text)),
+    }
+}
+
+/// The first whitespace-separated word, and everything after it
+fn next_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+fn parse_field_type(kind: &str, args: &str) -> Result<FieldType, String> {
+    let tokens: Vec<&str> = args.split_whitespace().collect();
+
+    let field_type = match kind {
+        "any" => FieldType::Any,
+        "year" => {
+            let (min, max) = parse_range(tokens.first().copied().unwrap_or(""))?;
+            FieldType::Year { min, max }
+        }
+        "range" => {
+            let (min, max) = parse_range(tokens.first().copied().unwrap_or(""))?;
+            FieldType::Range { min, max }
+        }
+        "regex" => {
+            if args.is_empty() {
+                return Err("regex needs a pattern".to_string());
+            }
+            // Patterns always have to match the whole value
+            let re = Regex::new(&format!("^(?:{})$", args))
+                .map_err(|e| format!("Bad regex '{}': {}", args, e))?;
+            FieldType::Regex(re)
+        }
+        "enum" => {
+            if tokens.is_empty() {
+                return Err("enum needs at least one value".to_string());
+            }
+            FieldType::Enum(tokens.iter().map(|t| t.to_string()).collect())
+        }
+        "measurement" => {
+            if tokens.is_empty() {
+                return Err("measurement needs at least one unit".to_string());
+            }
+            let units = tokens
+                .iter()
+                .map(|token| {
+                    let mut parts = token.splitn(2, ':');
+                    let unit = parts.next().unwrap_or("");
+                    let range = parts
+                        .next()
+                        .ok_or_else(|| format!("Expected unit:min..max, got '{}'", token))?;
+                    let (min, max) = parse_range(range)?;
+                    Ok(UnitRange {
+                        unit: unit.to_string(),
+                        min,
+                        max,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            FieldType::Measurement(units)
+        }
+        other => {
+            return Err(format!("Unrecognized field type '{}'", other));
+        }
+    };
+
+    // Everything but regex takes whitespace-separated arguments, so anything left over is a typo
+    let expected_tokens = match &field_type {
+        FieldType::Any => Some(0),
+        FieldType::Year { .. } | FieldType::Range { .. } => Some(1),
+        _ => None,
+    };
+    match expected_tokens {
+        Some(n) if n != tokens.len() => Err(format!("Unexpected arguments '{}'", args)),
+        _ => Ok(field_type),
+    }
+}
+
+impl Schema {
+    /// Reads a schema, one field per line: `<name> <required|optional> <type> [arguments]`.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> Result<Schema, String> {
+        let mut fields: Vec<FieldRule> = Vec::new();
+
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let describe = |e: String| format!("Schema line {}: {}", line_num + 1, e);
+
+            let (name, rest) = next_word(line);
+            let (flag, rest) = next_word(rest);
+            let required = match flag {
+                "required" => true,
+                "optional" => false,
+                _ => {
+                    return Err(describe(
+                        "Expected 'required' or 'optional' after the field name".to_string(),
+                    ));
+                }
+            };
+            let (kind, rest) = next_word(rest);
+            if kind.is_empty() {
+                return Err(describe("Missing field type".to_string()));
+            }
+            let args = rest.trim();
+
+            let field_type = parse_field_type(kind, args).map_err(describe)?;
+
+            if fields.iter().any(|f| f.name == name) {
+                return Err(describe(format!("Field {} defined twice", name)));
+            }
+
+            fields.push(FieldRule {
+                name: name.to_string(),
+                required,
+                field_type,
+            });
+        }
+
+        Ok(Schema { fields })
+    }
+
+    /// The rules for North Pole passports, as given in the puzzle
+    pub fn default_passport() -> Schema {
+        Schema::parse(DEFAULT_PASSPORT_SCHEMA).expect("Default schema should be valid")
+    }
+
+    pub fn fields(&self) -> &[FieldRule] {
+        &self.fields
+    }
+
+    pub fn has_required_fields<D: Document>(&self, doc: &D) -> bool {
+        self.fields
+            .iter()
+            .filter(|rule| rule.required)
+            .all(|rule| doc.field(&rule.name).is_some())
+    }
+
+    /// Everything wrong with the document: missing required fields, bad values, and fields the
+    /// schema doesn't know about
+    pub fn problems<D: Document>(&self, doc: &D) -> Vec<Problem> {
+        let mut out = Vec::new();
+
+        for rule in &self.fields {
+            let field = rule.name.clone();
+            match doc.field(&rule.name) {
+                None if rule.required => out.push(Problem::Missing { field }),
+                None => {}
+                Some(value) => {
+                    let value = value.to_string();
+                    match rule.field_type.check(&value) {
+                        Ok(()) => {}
+                        Err(ValueError::Malformed) => out.push(Problem::Malformed { field, value }),
+                        Err(ValueError::OutOfRange) => {
+                            out.push(Problem::OutOfRange { field, value })
+                        }
+                    }
+                }
+            }
+        }
+
+        for name in doc.field_names() {
+            if !self.fields.iter().any(|rule| rule.name == name) {
+                out.push(Problem::Unknown {
+                    field: name.to_string(),
+                });
+            }
+        }
+
+        out
+    }
+
+    /// Checks the document, giving back the first problem if there are any
+    pub fn validate<D: Document>(&self, doc: &D) -> Result<(), String> {
+        match self.problems(doc).first() {
+            Some(problem) => Err(problem.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    impl Document for HashMap<&str, &str> {
+        fn field(&self, name: &str) -> Option<&str> {
+            self.get(name).copied()
+        }
+
+        fn field_names(&self) -> Vec<&str> {
+            self.keys().copied().collect()
+        }
+    }
+
+    #[test]
+    fn default_schema_rules() {
+        let schema = Schema::default_passport();
+        let rule = |name: &str| {
+            &schema
+                .fields()
+                .iter()
+                .find(|f| f.name == name)
+                .unwrap()
+                .field_type
+        };
+
+        assert!(rule("byr").accepts("2002"));
+        assert_eq!(rule("byr").check("2003"), Err(ValueError::OutOfRange));
+        assert_eq!(rule("byr").check("02002"), Err(ValueError::Malformed));
+
+        assert!(rule("hgt").accepts("60in"));
+        assert!(rule("hgt").accepts("190cm"));
+        assert_eq!(rule("hgt").check("190in"), Err(ValueError::OutOfRange));
+        assert_eq!(rule("hgt").check("190"), Err(ValueError::Malformed));
+
+        assert!(rule("hcl").accepts("#123abc"));
+        assert!(!rule("hcl").accepts("#123abz"));
+        assert!(!rule("hcl").accepts("123abc"));
+
+        assert!(rule("ecl").accepts("brn"));
+        assert!(!rule("ecl").accepts("wat"));
+        assert!(!rule("ecl").accepts("brnn"));
+
+        assert!(rule("pid").accepts("000000001"));
+        assert!(!rule("pid").accepts("0123456789"));
+    }
+
+    #[test]
+    fn custom_schema() {
+        let schema = Schema::parse(
+            "# elf badges
+name required regex [A-Z][a-z]+
+age optional range 0..1000
+shift required enum day night",
+        )
+        .unwrap();
+
+        let mut badge: HashMap<&str, &str> = HashMap::new();
+        badge.insert("name", "Buddy");
+        assert!(!schema.has_required_fields(&badge));
+
+        badge.insert("shift", "night");
+        assert!(schema.has_required_fields(&badge));
+        assert!(schema.validate(&badge).is_ok());
+
+        badge.insert("age", "1001");
+        assert_eq!(
+            schema.validate(&badge),
+            Err("age out of range: \"1001\"".to_string())
+        );
+
+        badge.insert("name", "buddy");
+        badge.insert("hat", "pointy");
+        badge.remove("shift");
+        assert_eq!(
+            schema.problems(&badge),
+            vec![
+                Problem::Malformed {
+                    field: "name".to_string(),
+                    value: "buddy".to_string()
+                },
+                Problem::OutOfRange {
+                    field: "age".to_string(),
+                    value: "1001".to_string()
+                },
+                Problem::Missing {
+                    field: "shift".to_string()
+                },
+                Problem::Unknown {
+                    field: "hat".to_string()
+                },
+            ]
+        );
+
+        assert!(Schema::parse("name required colour").is_err());
+        assert!(Schema::parse("name required").is_err());
+
+        // Hand-edited files may line their columns up
+        let aligned = Schema::parse("byr  required\tyear   1920..2002").unwrap();
+        assert_eq!(aligned.fields()[0].name, "byr");
+        assert!(aligned.fields()[0].field_type.accepts("2002"));
+        assert!(Schema::parse("name sometimes any").is_err());
+        assert!(Schema::parse("age required range 10").is_err());
+        assert!(Schema::parse("a required any\na optional any").is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+
+use super::schema::{Document, Problem, Schema};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Height {
+    Cm(u32),
+    In(u32),
+}
+
+impl Height {
+    fn parse(value: &str) -> Option<Height> {
+        let digits = value.chars().take_while(|c| c.is_ascii_digit()).count();
+        let (amount, unit) = value.split_at(digits);
+        let amount = amount.parse::<u32>().ok()?;
+
+        match unit {
+            "cm" => Some(Height::Cm(amount)),
+            "in" => Some(Height::In(amount)),
+            _ => None,
+        }
+    }
+
+    pub fn to_cm(self) -> f64 {
+        match self {
+            Height::Cm(cm) => cm as f64,
+            Height::In(inches) => inches as f64 * 2.54,
+        }
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Height::Cm(cm) => write!(f, "{}cm", cm),
+            Height::In(inches) => write!(f, "{}in", inches),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EyeColor {
+    Amber,
+    Blue,
+    Brown,
+    Gray,
+    Green,
+    Hazel,
+    Other,
+}
+
+impl EyeColor {
+    fn parse(value: &str) -> Option<EyeColor> {
+        match value {
+            "amb" => Some(EyeColor::Amber),
+            "blu" => Some(EyeColor::Blue),
+            "brn" => Some(EyeColor::Brown),
+            "gry" => Some(EyeColor::Gray),
+            "grn" => Some(EyeColor::Green),
+            "hzl" => Some(EyeColor::Hazel),
+            "oth" => Some(EyeColor::Other),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            EyeColor::Amber => "amb",
+            EyeColor::Blue => "blu",
+            EyeColor::Brown => "brn",
+            EyeColor::Gray => "gry",
+            EyeColor::Green => "grn",
+            EyeColor::Hazel => "hzl",
+            EyeColor::Other => "oth",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    fn parse(value: &str) -> Option<Rgb> {
+        let hex = value.strip_prefix('#')?;
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+/// Always lowercase, so `#ABCDEF` and `#abcdef` come out the same
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// A passport which has passed validation, with its fields parsed into real types
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidPassport {
+    pub byr: u32,
+    pub iyr: u32,
+    pub eyr: u32,
+    pub hgt: Height,
+    pub hcl: Rgb,
+    pub ecl: EyeColor,
+    /// Kept as text, since the leading zeroes matter
+    pub pid: String,
+    pub cid: Option<String>,
+}
+
+fn typed<D: Document, T>(
+    doc: &D,
+    field: &str,
+    parse: impl Fn(&str) -> Option<T>,
+    problems: &mut Vec<Problem>,
+) -> Option<T> {
+    let field = field.to_string();
+    match doc.field(&field) {
+        None => {
+            problems.push(Problem::Missing { field });
+            None
+        }
+        Some(value) => {
+            let parsed = parse(value);
+            if parsed.is_none() {
+                problems.push(Problem::Malformed {
+                    field,
+                    value: value.to_string(),
+                });
+            }
+            parsed
+        }
+    }
+}
+
+impl ValidPassport {
+    /// Checks the document against the schema, then converts the fields. The conversion can
+    /// still fail if the schema is looser than the types, e.g. allowing another eye colour.
+    pub fn from_document<D: Document>(
+        doc: &D,
+        schema: &Schema,
+    ) -> Result<ValidPassport, Vec<Problem>> {
+        let mut problems = schema.problems(doc);
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        let year = |s: &str| s.parse::<u32>().ok();
+        let pid = |s: &str| Some(s.to_string());
+
+        let byr = typed(doc, "byr", year, &mut problems);
+        let iyr = typed(doc, "iyr", year, &mut problems);
+        let eyr = typed(doc, "eyr", year, &mut problems);
+        let hgt = typed(doc, "hgt", Height::parse, &mut problems);
+        let hcl = typed(doc, "hcl", Rgb::parse, &mut problems);
+        let ecl = typed(doc, "ecl", EyeColor::parse, &mut problems);
+        let pid = typed(doc, "pid", pid, &mut problems);
+
+        match (byr, iyr, eyr, hgt, hcl, ecl, pid) {
+            (Some(byr), Some(iyr), Some(eyr), Some(hgt), Some(hcl), Some(ecl), Some(pid)) => {
+                Ok(ValidPassport {
+                    byr,
+                    iyr,
+                    eyr,
+                    hgt,
+                    hcl,
+                    ecl,
+                    pid,
+                    cid: doc.field("cid").map(|s| s.to_string()),
+                })
+            }
+            _ => Err(problems),
+        }
+    }
+
+    /// The passport on a single line of the batch format, fields in a fixed order
+    pub fn to_batch_line(&self) -> String {
+        let mut out = format!(
+            "byr:{} iyr:{} eyr:{} hgt:{} hcl:{} ecl:{} pid:{}",
+            self.byr,
+            self.iyr,
+            self.eyr,
+            self.hgt,
+            self.hcl,
+            self.ecl.code(),
+            self.pid
+        );
+        if let Some(cid) = &self.cid {
+            out.push_str(" cid:");
+            out.push_str(cid);
+        }
+        out
+    }
+
+    fn to_csv_row(&self) -> String {
+        [
+            self.byr.to_string(),
+            self.iyr.to_string(),
+            self.eyr.to_string(),
+            self.hgt.to_string(),
+            format!("{:.2}", self.hgt.to_cm()),
+            self.hcl.to_string(),
+            self.ecl.code().to_string(),
+            self.pid.clone(),
+            self.cid.clone().unwrap_or_default(),
+        ]
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+/// Quotes a CSV field if it needs it. Custom schemas can let almost anything through as a
+/// `pid` or `cid`, so every column goes through here.
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Writes passports back out in the batch format, separated by blank lines
+pub fn to_batch(passports: &[ValidPassport]) -> String {
+    passports
+        .iter()
+        .map(|p| p.to_batch_line())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+pub fn to_csv(passports: &[ValidPassport]) -> String {
+    let mut lines = vec!["byr,iyr,eyr,hgt,hgt_cm,hcl,ecl,pid,cid".to_string()];
+    lines.extend(passports.iter().map(|p| p.to_csv_row()));
+    lines.join("\n")
+}
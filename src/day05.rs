@@ -1,62 +1,655 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use crate::read_input_arg;
+
 const INPUT: &str = include_str!("input/5.txt");
 
-#[inline(always)]
-fn read_seat_id(line: &str) -> u32 {
-    let mut seat = 0;
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Seat {
+    pub row: u32,
+    pub col: u32,
+}
+
+/// The shape of a plane: how many bits of the boarding pass pick the row and the column, and
+/// which letters mean "lower half" and "upper half" for each
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PlaneLayout {
+    row_bits: u32,
+    col_bits: u32,
+    /// (lower half, upper half); `('F', 'B')` for the puzzle
+    row_letters: (char, char),
+    /// (lower half, upper half); `('L', 'R')` for the puzzle
+    col_letters: (char, char),
+}
 
-    let mut chars = line.chars();
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PassError {
+    WrongLength {
+        expected: usize,
+        found: usize,
+    },
+    BadLetter {
+        /// 0-indexed character position in the pass
+        position: usize,
+        found: char,
+        expected: (char, char),
+    },
+    NoSuchSeat(Seat),
+}
 
-    for _ in 0..7 {
-        let c = chars.next().unwrap();
-        seat = (seat << 1) + (if c == 'B' { 1 } else { 0 });
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassError::WrongLength { expected, found } => write!(
+                f,
+                "Pass should be {} characters long, but was {}",
+                expected, found
+            ),
+            PassError::BadLetter {
+                position,
+                found,
+                expected: (lo, hi),
+            } => write!(
+                f,
+                "Unexpected '{}' at position {}; expected '{}' or '{}'",
+                found, position, lo, hi
+            ),
+            PassError::NoSuchSeat(seat) => write!(
+                f,
+                "There is no row {}, column {} on this plane",
+                seat.row, seat.col
+            ),
+        }
     }
+}
 
-    for _ in 0..3 {
-        let c = chars.next().unwrap();
-        seat = (seat << 1) + (if c == 'R' { 1 } else { 0 });
+/// The puzzle's plane: 128 rows of 8 seats
+impl Default for PlaneLayout {
+    fn default() -> PlaneLayout {
+        PlaneLayout {
+            row_bits: 7,
+            col_bits: 3,
+            row_letters: ('F', 'B'),
+            col_letters: ('L', 'R'),
+        }
     }
+}
 
-    assert!(chars.next().is_none());
+impl PlaneLayout {
+    pub fn new(
+        row_bits: u32,
+        col_bits: u32,
+        row_letters: (char, char),
+        col_letters: (char, char),
+    ) -> Result<PlaneLayout, String> {
+        // Widened so that huge counts are reported rather than wrapping round
+        let pass_len = u64::from(row_bits) + u64::from(col_bits);
+        if pass_len == 0 || pass_len > 31 {
+            return Err(format!(
+                "Passes need between 1 and 31 characters, not {}",
+                pass_len
+            ));
+        }
+        if row_letters.0 == row_letters.1 || col_letters.0 == col_letters.1 {
+            return Err("The two halves need different letters".to_string());
+        }
 
-    seat
+        Ok(PlaneLayout {
+            row_bits,
+            col_bits,
+            row_letters,
+            col_letters,
+        })
+    }
+
+    pub fn rows(&self) -> u32 {
+        1 << self.row_bits
+    }
+
+    pub fn cols(&self) -> u32 {
+        1 << self.col_bits
+    }
+
+    pub fn pass_len(&self) -> usize {
+        (self.row_bits + self.col_bits) as usize
+    }
+
+    pub fn seat_id(&self, seat: Seat) -> u32 {
+        (seat.row << self.col_bits) + seat.col
+    }
+
+    pub fn seat_from_id(&self, id: u32) -> Seat {
+        Seat {
+            row: id >> self.col_bits,
+            col: id & (self.cols() - 1),
+        }
+    }
+
+    fn check_seat(&self, seat: Seat) -> Result<(), PassError> {
+        if seat.row < self.rows() && seat.col < self.cols() {
+            Ok(())
+        } else {
+            Err(PassError::NoSuchSeat(seat))
+        }
+    }
+
+    /// Reads a boarding pass like `FBFBBFFRLR`
+    pub fn decode(&self, code: &str) -> Result<Seat, PassError> {
+        let found = code.chars().count();
+        if found != self.pass_len() {
+            return Err(PassError::WrongLength {
+                expected: self.pass_len(),
+                found,
+            });
+        }
+
+        let mut row = 0;
+        let mut col = 0;
+
+        for (position, c) in code.chars().enumerate() {
+            let (target, letters) = if position < self.row_bits as usize {
+                (&mut row, self.row_letters)
+            } else {
+                (&mut col, self.col_letters)
+            };
+
+            let bit = match c {
+                _ if c == letters.0 => 0,
+                _ if c == letters.1 => 1,
+                _ => {
+                    return Err(PassError::BadLetter {
+                        position,
+                        found: c,
+                        expected: letters,
+                    });
+                }
+            };
+            *target = (*target << 1) + bit;
+        }
+
+        Ok(Seat { row, col })
+    }
+
+    /// The boarding pass code for the seat, e.g. `FBFBBFFRLR`
+    pub fn encode(&self, seat: Seat) -> Result<String, PassError> {
+        self.check_seat(seat)?;
+
+        let bits = |value: u32, count: u32, (zero, one): (char, char)| {
+            (0..count)
+                .rev()
+                .map(move |i| if (value >> i) & 1 == 1 { one } else { zero })
+        };
+
+        Ok(bits(seat.row, self.row_bits, self.row_letters)
+            .chain(bits(seat.col, self.col_bits, self.col_letters))
+            .collect())
+    }
+
+    fn decode_id(&self, code: &str) -> Result<u32, PassError> {
+        self.decode(code).map(|seat| self.seat_id(seat))
+    }
 }
 
 fn run_5a_with_input(input: &str) -> u32 {
-    input.lines().map(|line| read_seat_id(line)).max().unwrap()
+    let layout = PlaneLayout::default();
+
+    input
+        .lines()
+        .map(|line| layout.decode_id(line).unwrap())
+        .max()
+        .unwrap()
 }
 
 pub fn run_5a() -> u32 {
     run_5a_with_input(INPUT)
 }
 
-fn run_5b_with_input(input: &str) -> u32 {
-    let mut seats = input
-        .lines()
-        .map(|line| read_seat_id(line))
-        .collect::<Vec<_>>();
-    seats.sort();
+/// A line of the manifest which isn't a boarding pass for this plane
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvalidPass {
+    /// 1-indexed
+    pub line: usize,
+    pub code: String,
+    pub error: PassError,
+}
+
+/// A seat which more than one boarding pass claims
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DuplicatePass {
+    pub id: u32,
+    /// 1-indexed, in file order
+    pub lines: Vec<usize>,
+}
+
+/// Everything odd about a list of boarding passes
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestReport {
+    pub layout: PlaneLayout,
+    /// Sorted, without duplicates
+    pub taken: Vec<u32>,
+    pub invalid: Vec<InvalidPass>,
+    pub duplicates: Vec<DuplicatePass>,
+    /// Rows before the first taken seat with nobody in them, as `(first, last)`
+    pub empty_front_rows: Option<(u32, u32)>,
+    /// Rows after the last taken seat with nobody in them, as `(first, last)`
+    pub empty_back_rows: Option<(u32, u32)>,
+    /// Every empty seat between the first and last taken seat
+    pub missing: Vec<u32>,
+}
 
-    for i in 1..seats.len() {
-        if seats[i - 1] + 2 == seats[i] {
-            return seats[i] - 1;
+impl ManifestReport {
+    /// Reads one boarding pass per line; blank lines are skipped
+    pub fn analyze(layout: &PlaneLayout, input: &str) -> ManifestReport {
+        let mut invalid = Vec::new();
+        let mut lines_by_id: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+
+        for (i, code) in input.lines().enumerate() {
+            let code = code.trim();
+            if code.is_empty() {
+                continue;
+            }
+
+            match layout.decode_id(code) {
+                Ok(id) => lines_by_id.entry(id).or_default().push(i + 1),
+                Err(error) => invalid.push(InvalidPass {
+                    line: i + 1,
+                    code: code.to_string(),
+                    error,
+                }),
+            }
+        }
+
+        let taken: Vec<u32> = lines_by_id.keys().copied().collect();
+        let duplicates = lines_by_id
+            .into_iter()
+            .filter(|(_, lines)| lines.len() > 1)
+            .map(|(id, lines)| DuplicatePass { id, lines })
+            .collect();
+
+        let (empty_front_rows, empty_back_rows, missing) = match (taken.first(), taken.last()) {
+            (Some(&first), Some(&last)) => {
+                let first_row = layout.seat_from_id(first).row;
+                let last_row = layout.seat_from_id(last).row;
+                let missing = taken.windows(2).flat_map(|w| w[0] + 1..w[1]).collect();
+                (
+                    Some((0, first_row))
+                        .filter(|_| first_row > 0)
+                        .map(|(a, b)| (a, b - 1)),
+                    Some((last_row + 1, layout.rows() - 1))
+                        .filter(|_| last_row + 1 < layout.rows()),
+                    missing,
+                )
+            }
+            _ => (None, None, Vec::new()),
+        };
+
+        ManifestReport {
+            layout: *layout,
+            taken,
+            invalid,
+            duplicates,
+            empty_front_rows,
+            empty_back_rows,
+            missing,
         }
     }
 
-    panic!("Seat not found, probably an input error");
+    /// Missing seats whose neighbours on both sides are taken; for the puzzle, there is
+    /// exactly one and it's ours
+    pub fn candidates(&self) -> Vec<u32> {
+        self.missing
+            .iter()
+            .copied()
+            .filter(|&id| {
+                id > 0
+                    && self.taken.binary_search(&(id - 1)).is_ok()
+                    && self.taken.binary_search(&(id + 1)).is_ok()
+            })
+            .collect()
+    }
+
+    pub fn to_text(&self) -> String {
+        let seat = |id: u32| {
+            let s = self.layout.seat_from_id(id);
+            format!("{} (row {}, column {})", id, s.row, s.col)
+        };
+        let rows = |range: Option<(u32, u32)>| match range {
+            None => "none".to_string(),
+            Some((a, b)) if a == b => a.to_string(),
+            Some((a, b)) => format!("{}-{}", a, b),
+        };
+
+        let mut lines = vec![format!("{} seats taken", self.taken.len())];
+        lines.extend(
+            self.invalid
+                .iter()
+                .map(|p| format!("line {}: invalid pass {:?}: {}", p.line, p.code, p.error)),
+        );
+        lines.extend(self.duplicates.iter().map(|d| {
+            let where_ = d.lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+            format!(
+                "duplicate seat {} on lines {}",
+                seat(d.id),
+                where_.join(", ")
+            )
+        }));
+        lines.push(format!(
+            "empty rows at the front: {}",
+            rows(self.empty_front_rows)
+        ));
+        lines.push(format!(
+            "empty rows at the back: {}",
+            rows(self.empty_back_rows)
+        ));
+        lines.extend(
+            self.missing
+                .iter()
+                .map(|&id| format!("missing seat {}", seat(id))),
+        );
+        lines.extend(
+            self.candidates()
+                .iter()
+                .map(|&id| format!("candidate seat {}", seat(id))),
+        );
+        lines.join("\n")
+    }
+}
+
+fn run_5b_with_input(input: &str) -> u32 {
+    let report = ManifestReport::analyze(&PlaneLayout::default(), input);
+
+    match *report.candidates().as_slice() {
+        [id] => id,
+        _ => panic!("Expected exactly one free seat:\n{}", report.to_text()),
+    }
 }
 
 pub fn run_5b() -> u32 {
     run_5b_with_input(INPUT)
 }
 
+/// Draws the whole plane, one line per row: `#` for a taken seat, `.` for an empty one, and
+/// `?` for each of the report's candidate seats. The aisle runs down the middle.
+pub fn render_seat_map(report: &ManifestReport) -> String {
+    let layout = &report.layout;
+    let taken: HashSet<u32> = report.taken.iter().copied().collect();
+    let candidates: HashSet<u32> = report.candidates().into_iter().collect();
+
+    let row_label_width = (layout.rows() - 1).to_string().len();
+
+    (0..layout.rows())
+        .map(|row| {
+            let seats: String = (0..layout.cols())
+                .map(|col| {
+                    let id = layout.seat_id(Seat { row, col });
+                    if taken.contains(&id) {
+                        '#'
+                    } else if candidates.contains(&id) {
+                        '?'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            let (left, right) = seats.split_at(layout.cols() as usize / 2);
+            format!(
+                "{:>width$} {} {}",
+                row,
+                left,
+                right,
+                width = row_label_width
+            )
+            .trim_end()
+            .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Pulls `--bits <row_bits>,<col_bits>` and `--letters <FBLR>` out of the arguments, leaving
+/// the rest
+fn parse_layout_args(args: &[String]) -> Result<(PlaneLayout, Vec<String>), String> {
+    let mut layout = PlaneLayout::default();
+    let mut rest = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bits" => {
+                let usage = "--bits expects <row_bits>,<col_bits>";
+                let bits = args
+                    .next()
+                    .ok_or(usage)?
+                    .split(',')
+                    .map(|b| {
+                        b.parse::<u32>()
+                            .map_err(|_| format!("Bad bit count '{}'; {}", b, usage))
+                    })
+                    .collect::<Result<Vec<u32>, String>>()?;
+                match *bits.as_slice() {
+                    [row_bits, col_bits] => {
+                        layout = PlaneLayout::new(
+                            row_bits,
+                            col_bits,
+                            layout.row_letters,
+                            layout.col_letters,
+                        )?;
+                    }
+                    _ => return Err(usage.to_string()),
+                }
+            }
+            "--letters" => {
+                let letters: Vec<char> =
+                    args.next().map(|s| s.chars().collect()).unwrap_or_default();
+                match *letters.as_slice() {
+                    [f, b, l, r] => {
+                        layout =
+                            PlaneLayout::new(layout.row_bits, layout.col_bits, (f, b), (l, r))?;
+                    }
+                    _ => return Err("--letters expects four letters, like FBLR".to_string()),
+                }
+            }
+            _ => rest.push(arg.clone()),
+        }
+    }
+
+    Ok((layout, rest))
+}
+
+/// CLI entry point: `5encode [--bits R,C] [--letters FBLR] (<id> | <row> <col>)`, printing the
+/// boarding pass code
+pub fn run_5encode(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 5encode [--bits R,C] [--letters FBLR] (<id> | <row> <col>)";
+
+    let (layout, args) = parse_layout_args(args)?;
+
+    let nums = args
+        .iter()
+        .map(|s| s.parse::<u32>().map_err(|_| usage.to_string()))
+        .collect::<Result<Vec<u32>, String>>()?;
+
+    let seat = match *nums.as_slice() {
+        [id] => layout.seat_from_id(id),
+        [row, col] => Seat { row, col },
+        _ => return Err(usage.to_string()),
+    };
+
+    let code = layout.encode(seat).map_err(|e| e.to_string())?;
+
+    Ok(format!(
+        "{} (row {}, column {}, id {})",
+        code,
+        seat.row,
+        seat.col,
+        layout.seat_id(seat)
+    ))
+}
+
+/// CLI entry point: `5check [--bits R,C] [--letters FBLR] [--input <file>]`, listing every
+/// missing seat, empty row, duplicate and invalid pass (by default, in the puzzle input)
+pub fn run_5check(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 5check [--bits R,C] [--letters FBLR] [--input <file>]";
+
+    let (input, rest) = read_input_arg(args, INPUT, usage)?;
+    let (layout, rest) = parse_layout_args(&rest)?;
+    if !rest.is_empty() {
+        return Err(usage.to_string());
+    }
+
+    Ok(ManifestReport::analyze(&layout, &input).to_text())
+}
+
+/// CLI entry point: `5map`, drawing the seat map for the puzzle input
+pub fn run_5map() -> String {
+    render_seat_map(&ManifestReport::analyze(&PlaneLayout::default(), INPUT))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn seat_parse_tests() {
-        assert_eq!(read_seat_id("BFFFBBFRRR"), 567);
-        assert_eq!(read_seat_id("FFFBBBFRRR"), 119);
-        assert_eq!(read_seat_id("BBFFBBFRLL"), 820);
+        let layout = PlaneLayout::default();
+        assert_eq!(layout.decode_id("BFFFBBFRRR"), Ok(567));
+        assert_eq!(layout.decode_id("FFFBBBFRRR"), Ok(119));
+        assert_eq!(layout.decode_id("BBFFBBFRLL"), Ok(820));
+    }
+
+    #[test]
+    fn seat_encode_tests() {
+        let layout = PlaneLayout::default();
+        assert_eq!(layout.decode("FBFBBFFRLR"), Ok(Seat { row: 44, col: 5 }));
+        assert_eq!(
+            layout.encode(Seat { row: 44, col: 5 }),
+            Ok("FBFBBFFRLR".to_string())
+        );
+        assert_eq!(
+            layout.encode(layout.seat_from_id(820)),
+            Ok("BBFFBBFRLL".to_string())
+        );
+
+        for id in 0..1024 {
+            let seat = layout.seat_from_id(id);
+            assert_eq!(layout.seat_id(seat), id);
+            assert_eq!(layout.decode(&layout.encode(seat).unwrap()), Ok(seat));
+        }
+    }
+
+    #[test]
+    fn custom_layouts() {
+        let big = PlaneLayout::new(8, 4, ('F', 'B'), ('L', 'R')).unwrap();
+        assert_eq!(big.rows(), 256);
+        assert_eq!(big.cols(), 16);
+        assert_eq!(big.decode("BBBBBBBBRRRR"), Ok(Seat { row: 255, col: 15 }));
+        assert_eq!(big.seat_id(Seat { row: 255, col: 15 }), 4095);
+
+        let numeric = PlaneLayout::new(2, 2, ('0', '1'), ('a', 'b')).unwrap();
+        assert_eq!(
+            numeric.encode(Seat { row: 2, col: 1 }),
+            Ok("10ab".to_string())
+        );
+
+        let layout = PlaneLayout::default();
+        assert_eq!(
+            layout.decode("FBFBBFFRL"),
+            Err(PassError::WrongLength {
+                expected: 10,
+                found: 9
+            })
+        );
+        assert_eq!(
+            layout.decode("FBFBBFFRLB"),
+            Err(PassError::BadLetter {
+                position: 9,
+                found: 'B',
+                expected: ('L', 'R')
+            })
+        );
+        assert_eq!(
+            layout.encode(Seat { row: 128, col: 0 }),
+            Err(PassError::NoSuchSeat(Seat { row: 128, col: 0 }))
+        );
+
+        assert!(PlaneLayout::new(20, 12, ('F', 'B'), ('L', 'R')).is_err());
+        assert_eq!(
+            PlaneLayout::new(u32::MAX, 2, ('F', 'B'), ('L', 'R')).unwrap_err(),
+            "Passes need between 1 and 31 characters, not 4294967297"
+        );
+        assert!(PlaneLayout::new(7, 3, ('F', 'F'), ('L', 'R')).is_err());
+
+        let args = |text: &str| text.split(' ').map(String::from).collect::<Vec<_>>();
+        let (layout, rest) = parse_layout_args(&args("--bits 8,4 12")).unwrap();
+        assert_eq!(
+            (layout.rows(), layout.cols(), rest),
+            (256, 16, vec!["12".to_string()])
+        );
+        assert_eq!(
+            parse_layout_args(&args("--bits 8,x,4")).unwrap_err(),
+            "Bad bit count 'x'; --bits expects <row_bits>,<col_bits>"
+        );
+        assert!(parse_layout_args(&args("--bits 8,4,2")).is_err());
+        assert!(parse_layout_args(&args("--bits")).is_err());
+    }
+
+    #[test]
+    fn manifest_report() {
+        let layout = PlaneLayout::new(2, 2, ('F', 'B'), ('L', 'R')).unwrap();
+        // Seats 5, 6, 8, 10 and 6 again, plus two broken lines; rows 0 and 3 are empty
+        let input = "FBLR\nFBRL\n\nBFLL\nBFRL\nFBRL\nFBX\nFBLX\n";
+        let report = ManifestReport::analyze(&layout, input);
+
+        assert_eq!(report.taken, vec![5, 6, 8, 10]);
+        assert_eq!(
+            report.duplicates,
+            vec![DuplicatePass {
+                id: 6,
+                lines: vec![2, 6]
+            }]
+        );
+        assert_eq!(report.invalid.len(), 2);
+        assert_eq!(report.invalid[0].line, 7);
+        assert_eq!(
+            report.invalid[1].error,
+            PassError::BadLetter {
+                position: 3,
+                found: 'X',
+                expected: ('L', 'R')
+            }
+        );
+        assert_eq!(report.empty_front_rows, Some((0, 0)));
+        assert_eq!(report.empty_back_rows, Some((3, 3)));
+        assert_eq!(report.missing, vec![7, 9]);
+        assert_eq!(report.candidates(), vec![7, 9]);
+
+        let empty = ManifestReport::analyze(&layout, "");
+        assert_eq!(empty.missing, Vec::<u32>::new());
+        assert_eq!(empty.empty_front_rows, None);
+    }
+
+    #[test]
+    fn seat_map() {
+        let layout = PlaneLayout::default();
+        let passes = |ids: &[u32]| {
+            ids.iter()
+                .map(|&id| layout.encode(layout.seat_from_id(id)).unwrap())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let ids: Vec<u32> = (10..20).filter(|&id| id != 13).collect();
+        let map = render_seat_map(&ManifestReport::analyze(&layout, &passes(&ids)));
+        let rows: Vec<&str> = map.lines().collect();
+
+        assert_eq!(rows.len(), 128);
+        assert_eq!(rows[0], "  0 .... ....");
+        assert_eq!(rows[1], "  1 ..## #?##");
+        assert_eq!(rows[2], "  2 #### ....");
+
+        // Every candidate is marked, not just the first
+        let map = render_seat_map(&ManifestReport::analyze(&layout, &passes(&[8, 10, 12])));
+        assert_eq!(map.lines().nth(1).unwrap(), "  1 #?#? #...");
     }
 }
@@ -1,86 +1,462 @@
+use std::collections::BTreeMap;
+
+use crate::{json_string, read_input_arg};
+
 const INPUT: &str = include_str!("input/6.txt");
 
-const LETTER_OFFSET: u8 = 'a' as u8;
+const LETTER_OFFSET: u8 = b'a';
 
-#[derive(Default, Eq, PartialEq, Debug, Copy, Clone)]
-struct CustomsGroup {
-    seen: [bool; 26],
+/// The questions someone answered "yes" to. Questions `a` to `z` are one bit each; anything
+/// else (any other character) goes in a sorted list beside them.
+#[derive(Default, Eq, PartialEq, Debug, Clone)]
+pub struct Answers {
+    bits: u32,
+    others: Vec<char>,
 }
 
-impl CustomsGroup {
-    fn intersect(&mut self, other: CustomsGroup) {
-        for i in 0..self.seen.len() {
-            self.seen[i] &= other.seen[i];
+fn ascii_index(question: char) -> Option<u32> {
+    match question {
+        'a'..='z' => Some((question as u8 - LETTER_OFFSET) as u32),
+        _ => None,
+    }
+}
+
+/// Merges two sorted lists, keeping the items `keep(in a, in b)` says to
+fn merge(a: &[char], b: &[char], keep: fn(bool, bool) -> bool) -> Vec<char> {
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        let (c, in_a, in_b) = match (a.get(i), b.get(j)) {
+            (Some(&x), Some(&y)) if x == y => (x, true, true),
+            (Some(&x), Some(&y)) if x < y => (x, true, false),
+            (Some(&x), None) => (x, true, false),
+            (_, Some(&y)) => (y, false, true),
+            (None, None) => unreachable!(),
+        };
+        if in_a {
+            i += 1;
+        }
+        if in_b {
+            j += 1;
+        }
+        if keep(in_a, in_b) {
+            out.push(c);
         }
     }
+
+    out
 }
 
-fn run_6a_with_input(input: &str) -> usize {
-    let mut current_group = CustomsGroup::default();
-    let mut total_count = 0;
+impl Answers {
+    /// Every character is a question, except whitespace
+    pub fn parse(line: &str) -> Answers {
+        let mut answers = Answers::default();
+        for c in line.chars().filter(|c| !c.is_whitespace()) {
+            match ascii_index(c) {
+                Some(i) => answers.bits |= 1 << i,
+                None => answers.others.push(c),
+            }
+        }
+        answers.others.sort_unstable();
+        answers.others.dedup();
+        answers
+    }
 
-    for line in input.lines() {
-        if line.is_empty() {
-            total_count += current_group.seen.iter().copied().filter(|&b| b).count();
-            current_group = CustomsGroup::default();
-        } else {
-            for c in line.chars() {
-                match c {
-                    'a'..='z' => {
-                        let index: usize = (c as u8 - LETTER_OFFSET) as usize;
-                        current_group.seen[index] = true;
-                    }
-                    _ => {
-                        panic!("Unsupported character '{}'", c);
-                    }
+    pub fn contains(&self, question: char) -> bool {
+        match ascii_index(question) {
+            Some(i) => self.bits & (1 << i) != 0,
+            None => self.others.binary_search(&question).is_ok(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones() as usize + self.others.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0 && self.others.is_empty()
+    }
+
+    pub fn union(&self, other: &Answers) -> Answers {
+        Answers {
+            bits: self.bits | other.bits,
+            others: merge(&self.others, &other.others, |a, b| a || b),
+        }
+    }
+
+    pub fn intersection(&self, other: &Answers) -> Answers {
+        Answers {
+            bits: self.bits & other.bits,
+            others: merge(&self.others, &other.others, |a, b| a && b),
+        }
+    }
+
+    pub fn difference(&self, other: &Answers) -> Answers {
+        Answers {
+            bits: self.bits & !other.bits,
+            others: merge(&self.others, &other.others, |a, b| a && !b),
+        }
+    }
+
+    /// `a` to `z` first, then everything else in code point order
+    pub fn questions(&self) -> impl Iterator<Item = char> + '_ {
+        (0..26)
+            .filter(move |i| self.bits & (1 << i) != 0)
+            .map(|i| (LETTER_OFFSET + i as u8) as char)
+            .chain(self.others.iter().copied())
+    }
+}
+
+/// The answers of each person in one group
+#[derive(Default, Eq, PartialEq, Debug, Clone)]
+pub struct CustomsGroup {
+    people: Vec<Answers>,
+}
+
+impl CustomsGroup {
+    pub fn people(&self) -> &[Answers] {
+        &self.people
+    }
+
+    /// Questions anyone in the group answered
+    pub fn union(&self) -> Answers {
+        self.people
+            .iter()
+            .fold(Answers::default(), |acc, p| acc.union(p))
+    }
+
+    /// Questions everyone in the group answered
+    pub fn intersection(&self) -> Answers {
+        match self.people.split_first() {
+            None => Answers::default(),
+            Some((first, rest)) => rest
+                .iter()
+                .fold(first.clone(), |acc, p| acc.intersection(p)),
+        }
+    }
+
+    /// Questions whose number of answers passes the check. Only questions somebody answered
+    /// are considered, so `exactly(0)` is always empty.
+    fn answered_by(&self, check: impl Fn(usize) -> bool) -> Answers {
+        let mut counts = [0; 26];
+        let mut other_counts: BTreeMap<char, usize> = BTreeMap::new();
+        for person in &self.people {
+            for (i, count) in counts.iter_mut().enumerate() {
+                if person.bits & (1 << i) != 0 {
+                    *count += 1;
                 }
             }
+            for &c in &person.others {
+                *other_counts.entry(c).or_default() += 1;
+            }
         }
+
+        let bits = counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0 && check(count))
+            .fold(0, |bits, (i, _)| bits | (1 << i));
+        let others = other_counts
+            .into_iter()
+            .filter(|&(_, count)| check(count))
+            .map(|(c, _)| c)
+            .collect();
+        Answers { bits, others }
     }
 
-    total_count += current_group.seen.iter().copied().filter(|&b| b).count();
+    pub fn at_least(&self, people: usize) -> Answers {
+        self.answered_by(|count| count >= people)
+    }
+
+    pub fn at_most(&self, people: usize) -> Answers {
+        self.answered_by(|count| count <= people)
+    }
 
-    total_count
+    pub fn exactly(&self, people: usize) -> Answers {
+        self.answered_by(|count| count == people)
+    }
 }
 
-fn run_6b_with_input(input: &str) -> usize {
-    let mut current_group: Option<CustomsGroup> = None;
-    let mut total_count = 0;
+/// Groups are separated by blank lines, with one person per line
+pub fn parse_groups(input: &str) -> Vec<CustomsGroup> {
+    let mut groups = Vec::new();
+    let mut current = CustomsGroup::default();
 
     for line in input.lines() {
+        let line = line.trim();
         if line.is_empty() {
-            total_count += current_group
-                .map(|cg| cg.seen.iter().copied().filter(|&b| b).count())
-                .unwrap_or(0);
-            current_group = None;
+            if !current.people.is_empty() {
+                groups.push(std::mem::take(&mut current));
+            }
         } else {
-            let mut person = CustomsGroup::default();
-            for c in line.chars() {
-                match c {
-                    'a'..='z' => {
-                        let index: usize = (c as u8 - LETTER_OFFSET) as usize;
-                        person.seen[index] = true;
-                    }
-                    _ => {
-                        panic!("Unsupported character '{}'", c);
-                    }
+            current.people.push(Answers::parse(line));
+        }
+    }
+
+    if !current.people.is_empty() {
+        groups.push(current);
+    }
+
+    groups
+}
+
+/// A set of questions, worked out separately for each group
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum QuestionSet {
+    Union,
+    Intersection,
+    AtLeast(usize),
+    AtMost(usize),
+    Exactly(usize),
+    /// `a & b`
+    Both(Box<QuestionSet>, Box<QuestionSet>),
+    /// `a | b`
+    Either(Box<QuestionSet>, Box<QuestionSet>),
+    /// `a - b`
+    Minus(Box<QuestionSet>, Box<QuestionSet>),
+}
+
+impl QuestionSet {
+    pub fn evaluate(&self, group: &CustomsGroup) -> Answers {
+        match self {
+            QuestionSet::Union => group.union(),
+            QuestionSet::Intersection => group.intersection(),
+            QuestionSet::AtLeast(n) => group.at_least(*n),
+            QuestionSet::AtMost(n) => group.at_most(*n),
+            QuestionSet::Exactly(n) => group.exactly(*n),
+            QuestionSet::Both(a, b) => a.evaluate(group).intersection(&b.evaluate(group)),
+            QuestionSet::Either(a, b) => a.evaluate(group).union(&b.evaluate(group)),
+            QuestionSet::Minus(a, b) => a.evaluate(group).difference(&b.evaluate(group)),
+        }
+    }
+}
+
+/// `count(<set>)`, where the set is `union`, `intersection`, `at_least(N)`, `at_most(N)`,
+/// `exactly(N)` or `exactly_one`, combined with `&`, `|`, `-` and parentheses (all the same
+/// precedence, left to right)
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Query {
+    set: QuestionSet,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, String> {
+        parse_query::parse(text).map(|set| Query { set })
+    }
+
+    pub fn count(&self, group: &CustomsGroup) -> usize {
+        self.set.evaluate(group).len()
+    }
+
+    /// The count for each group, added up
+    pub fn total(&self, groups: &[CustomsGroup]) -> usize {
+        groups.iter().map(|g| self.count(g)).sum()
+    }
+}
+
+mod parse_query {
+    use super::QuestionSet;
+
+    use nom::{
+        branch::alt,
+        bytes::complete::tag,
+        character::complete::{char as exact_char, one_of, space0},
+        combinator::{eof, map, value},
+        multi::fold_many0,
+        sequence::{delimited, pair, terminated, tuple},
+        IResult,
+    };
+
+    use crate::parse_usize;
+
+    fn counted<'a>(
+        name: &'static str,
+        make: fn(usize) -> QuestionSet,
+    ) -> impl FnMut(&'a str) -> IResult<&'a str, QuestionSet> {
+        map(
+            delimited(
+                tuple((tag(name), space0, exact_char('('), space0)),
+                parse_usize,
+                pair(space0, exact_char(')')),
+            ),
+            make,
+        )
+    }
+
+    fn term(input: &str) -> IResult<&str, QuestionSet> {
+        alt((
+            value(QuestionSet::Union, tag("union")),
+            value(QuestionSet::Intersection, tag("intersection")),
+            value(QuestionSet::Exactly(1), tag("exactly_one")),
+            counted("at_least", QuestionSet::AtLeast),
+            counted("at_most", QuestionSet::AtMost),
+            counted("exactly", QuestionSet::Exactly),
+            delimited(
+                pair(exact_char('('), space0),
+                set,
+                pair(space0, exact_char(')')),
+            ),
+        ))(input)
+    }
+
+    fn set(input: &str) -> IResult<&str, QuestionSet> {
+        let (input, first) = term(input)?;
+        fold_many0(
+            pair(delimited(space0, one_of("&|-"), space0), term),
+            first,
+            |acc, (op, next)| {
+                let (a, b) = (Box::new(acc), Box::new(next));
+                match op {
+                    '&' => QuestionSet::Both(a, b),
+                    '|' => QuestionSet::Either(a, b),
+                    _ => QuestionSet::Minus(a, b),
                 }
+            },
+        )(input)
+    }
+
+    pub(super) fn parse(input: &str) -> Result<QuestionSet, String> {
+        let (_, set) = terminated(
+            delimited(
+                tuple((space0, tag("count"), space0, exact_char('('), space0)),
+                set,
+                tuple((space0, exact_char(')'), space0)),
+            ),
+            eof,
+        )(input)
+        .map_err(|e| format!("Could not parse query '{}': {:?}", input, e))?;
+
+        Ok(set)
+    }
+}
+
+/// How many people answered each question, over one group or many
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct QuestionStats {
+    pub groups: usize,
+    pub people: usize,
+    /// Every question from a to z, even ones nobody answered, plus any other characters seen
+    pub counts: BTreeMap<char, usize>,
+}
+
+impl QuestionStats {
+    fn empty() -> QuestionStats {
+        QuestionStats {
+            groups: 0,
+            people: 0,
+            counts: ('a'..='z').map(|q| (q, 0)).collect(),
+        }
+    }
+
+    pub fn of_group(group: &CustomsGroup) -> QuestionStats {
+        QuestionStats::of_groups(std::slice::from_ref(group))
+    }
+
+    pub fn of_groups(groups: &[CustomsGroup]) -> QuestionStats {
+        let mut stats = QuestionStats::empty();
+        stats.groups = groups.len();
+
+        for person in groups.iter().flat_map(|g| g.people()) {
+            stats.people += 1;
+            for q in person.questions() {
+                *stats.counts.entry(q).or_default() += 1;
             }
-            current_group = match current_group {
-                None => Some(person),
-                Some(mut existing) => {
-                    existing.intersect(person);
-                    Some(existing)
-                }
-            };
         }
+
+        stats
+    }
+
+    pub fn count(&self, question: char) -> usize {
+        self.counts.get(&question).copied().unwrap_or(0)
+    }
+
+    fn questions_with(&self, pick: fn(usize, usize) -> usize) -> Vec<char> {
+        let answered = self.counts.iter().filter(|&(_, &n)| n > 0);
+        match answered.clone().map(|(_, &n)| n).reduce(pick) {
+            None => Vec::new(),
+            Some(best) => answered
+                .filter(|&(_, &n)| n == best)
+                .map(|(&q, _)| q)
+                .collect(),
+        }
+    }
+
+    /// All the questions tied for the most answers; empty if nobody answered anything
+    pub fn most_common(&self) -> Vec<char> {
+        self.questions_with(usize::max)
+    }
+
+    /// All the questions tied for the fewest answers, out of the ones somebody answered
+    pub fn least_common(&self) -> Vec<char> {
+        self.questions_with(usize::min)
+    }
+
+    /// One line per question with its count and a bar, scaled so the longest is `width`
+    pub fn histogram(&self, width: usize) -> String {
+        let max = self.counts.values().copied().max().unwrap_or(0).max(1);
+
+        self.counts
+            .iter()
+            .map(|(q, &n)| {
+                let bar = "#".repeat((n * width).div_ceil(max));
+                format!("{} {:>5} {}", q, n, bar).trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_table(&self) -> String {
+        let list = |qs: Vec<char>| {
+            let n = qs.first().map(|&q| self.count(q)).unwrap_or(0);
+            let qs: Vec<String> = qs.iter().map(|q| q.to_string()).collect();
+            if qs.is_empty() {
+                "none".to_string()
+            } else {
+                format!("{} ({})", qs.join(", "), n)
+            }
+        };
+
+        format!(
+            "{} people in {} groups\n{}\nmost common: {}\nleast common: {}",
+            self.people,
+            self.groups,
+            self.histogram(40),
+            list(self.most_common()),
+            list(self.least_common())
+        )
     }
 
-    total_count += current_group
-        .map(|cg| cg.seen.iter().copied().filter(|&b| b).count())
-        .unwrap_or(0);
+    pub fn to_json(&self) -> String {
+        let list = |qs: Vec<char>| {
+            let qs: Vec<String> = qs.iter().map(|q| json_string(&q.to_string())).collect();
+            format!("[{}]", qs.join(","))
+        };
+        let counts: Vec<String> = self
+            .counts
+            .iter()
+            .map(|(q, n)| format!("{}:{}", json_string(&q.to_string()), n))
+            .collect();
+
+        format!(
+            "{{\"groups\":{},\"people\":{},\"counts\":{{{}}},\"most_common\":{},\"least_common\":{}}}",
+            self.groups,
+            self.people,
+            counts.join(","),
+            list(self.most_common()),
+            list(self.least_common())
+        )
+    }
+}
+
+fn run_6a_with_input(input: &str) -> usize {
+    let groups = parse_groups(input);
+    groups.iter().map(|g| g.union().len()).sum()
+}
 
-    total_count
+fn run_6b_with_input(input: &str) -> usize {
+    let groups = parse_groups(input);
+    groups.iter().map(|g| g.intersection().len()).sum()
 }
 
 pub fn run_6a() -> usize {
@@ -91,6 +467,67 @@ pub fn run_6b() -> usize {
     run_6b_with_input(INPUT)
 }
 
+/// CLI entry point: `6query <query> [--input <file>]`, e.g. `6query 'count(at_least(2))'`
+pub fn run_6query(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 6query <query> [--input <file>]";
+
+    let (input, rest) = read_input_arg(args, INPUT, usage)?;
+    let query = match rest.as_slice() {
+        [query] => query,
+        _ => return Err(usage.to_string()),
+    };
+
+    let query = Query::parse(query)?;
+    let groups = parse_groups(&input);
+    Ok(query.total(&groups).to_string())
+}
+
+/// CLI entry point: `6stats [--format table|json] [--groups] [--input <file>]`, showing how
+/// often each question was answered over the whole file, and with `--groups` for each group too
+pub fn run_6stats(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 6stats [--format table|json] [--groups] [--input <file>]";
+
+    let (input, rest) = read_input_arg(args, INPUT, usage)?;
+    let mut json = false;
+    let mut per_group = false;
+
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(|s| s.as_str()) {
+                Some("table") => json = false,
+                Some("json") => json = true,
+                _ => return Err(usage.to_string()),
+            },
+            "--groups" => per_group = true,
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let groups = parse_groups(&input);
+    let total = QuestionStats::of_groups(&groups);
+    let each = || groups.iter().map(QuestionStats::of_group);
+
+    Ok(match (json, per_group) {
+        (false, false) => total.to_table(),
+        (false, true) => each()
+            .enumerate()
+            .map(|(i, stats)| format!("group {}: {}", i + 1, stats.to_table()))
+            .chain(std::iter::once(format!("all groups: {}", total.to_table())))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        (true, false) => total.to_json(),
+        (true, true) => {
+            let each: Vec<String> = each().map(|stats| stats.to_json()).collect();
+            format!(
+                "{{\"total\":{},\"groups\":[{}]}}",
+                total.to_json(),
+                each.join(",")
+            )
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -120,4 +557,75 @@ b";
     fn sample_6b() {
         assert_eq!(run_6b_with_input(SAMPLE), 6);
     }
+
+    #[test]
+    fn queries() {
+        let groups = parse_groups(SAMPLE);
+        let total = |text: &str| Query::parse(text).unwrap().total(&groups);
+
+        assert_eq!(total("count(union)"), 11);
+        assert_eq!(total("count(intersection)"), 6);
+        assert_eq!(total("count(at_least(2))"), 2);
+        assert_eq!(total("count(exactly_one)"), 9);
+        assert_eq!(total("count(at_most(1))"), 9);
+        assert_eq!(total("count(union - intersection)"), 5);
+        assert_eq!(total("count( (union - exactly(1)) | intersection )"), 6);
+        assert_eq!(total("count(at_least(2) & exactly_one)"), 0);
+
+        assert!(Query::parse("count(everything)").is_err());
+        assert!(Query::parse("count(union").is_err());
+    }
+
+    #[test]
+    fn question_stats() {
+        let groups = parse_groups(SAMPLE);
+        let stats = QuestionStats::of_groups(&groups);
+
+        assert_eq!(stats.groups, 5);
+        assert_eq!(stats.people, 11);
+        assert_eq!(stats.count('a'), 8);
+        assert_eq!(stats.count('b'), 4);
+        assert_eq!(stats.count('c'), 3);
+        assert_eq!(stats.count('z'), 0);
+        assert_eq!(stats.most_common(), vec!['a']);
+        assert_eq!(stats.least_common(), vec!['c']);
+
+        let third = QuestionStats::of_group(&groups[2]);
+        assert_eq!(third.most_common(), vec!['a']);
+        assert_eq!(third.least_common(), vec!['b', 'c']);
+        assert!(third
+            .histogram(4)
+            .starts_with("a     2 ####\nb     1 ##\nc     1 ##\nd     0\n"));
+        assert!(third
+            .to_json()
+            .starts_with("{\"groups\":1,\"people\":2,\"counts\":{\"a\":2,\"b\":1,"));
+
+        let nobody = QuestionStats::of_groups(&[]);
+        assert_eq!(nobody.most_common(), Vec::<char>::new());
+    }
+
+    #[test]
+    fn unicode_answers() {
+        let groups = parse_groups("aé日Z\né日\nZé a\n\nñ\nN");
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].people()[2].len(), 3);
+        assert_eq!(groups[0].union().questions().collect::<String>(), "aZé日");
+        assert_eq!(
+            groups[0].intersection().questions().collect::<String>(),
+            "é"
+        );
+        assert_eq!(groups[0].exactly(2).questions().collect::<String>(), "aZ日");
+        assert!(groups[1].union().contains('ñ'));
+        assert!(!groups[1].union().contains('n'));
+
+        let query = Query::parse("count(union - at_least(3))").unwrap();
+        assert_eq!(query.total(&groups), 3 + 2);
+
+        let stats = QuestionStats::of_groups(&groups);
+        assert_eq!(stats.count('é'), 3);
+        assert_eq!(stats.count('N'), 1);
+        assert_eq!(stats.most_common(), vec!['é']);
+        assert_eq!(stats.counts.len(), 26 + 5);
+    }
 }
@@ -1,10 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::read_input_arg;
 
 const INPUT: &str = include_str!("input/7.txt");
 
 mod parse {
-    use std::collections::HashMap;
-
     use nom::{
         branch::alt,
         bytes::complete::tag,
@@ -15,20 +17,18 @@ mod parse {
         IResult,
     };
 
-    use crate::lib::parse_usize;
+    use crate::parse_usize;
 
-    struct Rule {
-        name: String,
-        children: HashMap<String, usize>,
+    pub(super) struct Rule {
+        pub(super) name: String,
+        /// In the order the rule lists them
+        pub(super) children: Vec<(String, usize)>,
     }
 
     fn parse_bag_descr(input: &str) -> IResult<&str, String> {
         map(
             tuple((alpha1, exact_char(' '), alpha1)),
-            |(adj1, _, adj2): (&str, _, &str)| {
-                let out = adj1.to_string() + " " + adj2;
-                out
-            },
+            |(adj1, _, adj2): (&str, _, &str)| adj1.to_string() + " " + adj2,
         )(input)
     }
 
@@ -36,9 +36,9 @@ mod parse {
         alt((tag("bags"), tag("bag")))(input)
     }
 
-    fn parse_bag_contents(input: &str) -> IResult<&str, HashMap<String, usize>> {
+    fn parse_bag_contents(input: &str) -> IResult<&str, Vec<(String, usize)>> {
         alt((
-            map(tag("no other bags."), |_| HashMap::new()),
+            map(tag("no other bags."), |_| Vec::new()),
             map(
                 pair(
                     separated_list1(
@@ -53,12 +53,10 @@ mod parse {
                     ),
                     exact_char('.'),
                 ),
-                |(list, _): (Vec<(usize, char, String, char, &str)>, char)| {
-                    let mut out = HashMap::new();
-                    for (num, _, kind, _, _) in list {
-                        out.insert(kind, num);
-                    }
-                    out
+                |(list, _)| {
+                    list.into_iter()
+                        .map(|(num, _, kind, _, _)| (kind, num))
+                        .collect()
                 },
             ),
         ))(input)
@@ -67,7 +65,7 @@ mod parse {
     fn parse_line(input: &str) -> IResult<&str, Rule> {
         map(
             tuple((parse_bag_descr, tag(" bags contain "), parse_bag_contents)),
-            |(descr, _, contents): (String, _, HashMap<String, usize>)| Rule {
+            |(descr, _, contents): (String, _, Vec<(String, usize)>)| Rule {
                 name: descr,
                 children: contents,
             },
@@ -80,58 +78,697 @@ mod parse {
         Ok(("", rules))
     }
 
-    pub(super) fn parse(input: &str) -> HashMap<String, HashMap<String, usize>> {
-        let (_, out) = parse_helper(input).unwrap();
-        out.into_iter().fold(HashMap::new(), |mut acc, next| {
-            acc.insert(next.name, next.children);
-            acc
-        })
+    pub(super) fn parse(input: &str) -> Result<Vec<Rule>, String> {
+        let (_, rules) = parse_helper(input.trim_end())
+            .map_err(|e| format!("Could not parse bag rules: {:?}", e))?;
+        Ok(rules)
     }
 }
 
-fn run_7a_with_input(input: &str) -> usize {
-    let rules = parse::parse(input);
+/// An index into a `BagGraph`
+pub type BagId = usize;
+
+/// The bag rules, with every colour given a small id. Edges are stored both ways: what each bag
+/// holds, and what holds each bag.
+#[derive(Debug, Clone, Default)]
+pub struct BagGraph {
+    names: Vec<String>,
+    ids: HashMap<String, BagId>,
+    /// `children[bag]` is `(child, how many)` in rule order
+    children: Vec<Vec<(BagId, usize)>>,
+    /// `parents[bag]` is `(parent, how many)`
+    parents: Vec<Vec<(BagId, usize)>>,
+    /// The lines with a rule for each bag: none if it's only ever mentioned inside other
+    /// bags, several if it's defined more than once
+    rule_lines: Vec<Vec<usize>>,
+}
+
+/// Something wrong with a set of bag rules
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RuleProblem {
+    /// A bag which ends up inside itself; the first and last names are the same
+    Cycle(Vec<String>),
+    /// A bag used by another rule which has no rule of its own
+    Undefined { bag: String, used_by: String },
+    /// 1-indexed lines
+    Duplicate { bag: String, lines: Vec<usize> },
+    /// A bag which neither holds nor can be held by the bag being asked about
+    Unreachable { bag: String },
+}
+
+impl RuleProblem {
+    /// Whether the rules can't be solved at all; unreachable bags are only noise
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, RuleProblem::Unreachable { .. })
+    }
+}
+
+impl fmt::Display for RuleProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleProblem::Cycle(path) => write!(f, "cycle: {}", path.join(" -> ")),
+            RuleProblem::Undefined { bag, used_by } => {
+                write!(f, "'{}' (inside '{}') has no rule", bag, used_by)
+            }
+            RuleProblem::Duplicate { bag, lines } => {
+                let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+                write!(f, "'{}' has rules on lines {}", bag, lines.join(", "))
+            }
+            RuleProblem::Unreachable { bag } => write!(f, "'{}' is unrelated", bag),
+        }
+    }
+}
+
+impl BagGraph {
+    pub fn parse(input: &str) -> Result<BagGraph, String> {
+        let rules = parse::parse(input)?;
+
+        let mut graph = BagGraph::default();
+        for (i, rule) in rules.into_iter().enumerate() {
+            let parent = graph.intern(&rule.name);
+            graph.rule_lines[parent].push(i + 1);
+            // Only the first rule counts; `problems` reports the rest
+            if graph.rule_lines[parent].len() > 1 {
+                continue;
+            }
+
+            for (child, count) in rule.children {
+                let child = graph.intern(&child);
+                graph.children[parent].push((child, count));
+                graph.parents[child].push((parent, count));
+            }
+        }
+
+        Ok(graph)
+    }
+
+    fn intern(&mut self, name: &str) -> BagId {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        self.children.push(Vec::new());
+        self.parents.push(Vec::new());
+        self.rule_lines.push(Vec::new());
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// How many rule lines were read, duplicates included. This can be fewer than `len`, since
+    /// bags which are only mentioned inside other bags have no rule.
+    pub fn rule_count(&self) -> usize {
+        self.rule_lines.iter().map(|lines| lines.len()).sum()
+    }
+
+    pub fn id(&self, name: &str) -> Option<BagId> {
+        self.ids.get(name).copied()
+    }
+
+    /// Like `id`, but an unknown colour is an error
+    pub fn lookup(&self, name: &str) -> Result<BagId, String> {
+        self.id(name)
+            .ok_or_else(|| format!("No rule mentions a '{}' bag", name))
+    }
+
+    pub fn name(&self, bag: BagId) -> &str {
+        &self.names[bag]
+    }
+
+    /// `(child, how many)` for each kind of bag directly inside this one
+    pub fn direct_children(&self, bag: BagId) -> &[(BagId, usize)] {
+        &self.children[bag]
+    }
+
+    /// `(parent, how many)` for each kind of bag which directly holds this one
+    pub fn direct_parents(&self, bag: BagId) -> &[(BagId, usize)] {
+        &self.parents[bag]
+    }
+
+    /// Every bag which eventually holds this one, in id order
+    pub fn containers_of(&self, bag: BagId) -> Vec<BagId> {
+        let mut seen = vec![false; self.len()];
+        let mut queue: VecDeque<BagId> = VecDeque::from(vec![bag]);
+
+        while let Some(next) = queue.pop_front() {
+            for &(parent, _) in self.direct_parents(next) {
+                if !seen[parent] {
+                    seen[parent] = true;
+                    queue.push_back(parent);
+                }
+            }
+        }
+
+        (0..self.len())
+            .filter(|&id| seen[id] && id != bag)
+            .collect()
+    }
+
+    /// Every way a bag ends up inside itself, each cycle reported once from where the search
+    /// first ran into it
+    fn cycles(&self) -> Vec<Vec<BagId>> {
+        #[derive(Copy, Clone, PartialEq)]
+        enum State {
+            New,
+            OnPath,
+            Done,
+        }
+
+        let mut state = vec![State::New; self.len()];
+        let mut out = Vec::new();
+
+        for root in 0..self.len() {
+            if state[root] != State::New {
+                continue;
+            }
+
+            // The current path, each bag with the index of the next child to look at
+            state[root] = State::OnPath;
+            let mut path: Vec<(BagId, usize)> = vec![(root, 0)];
+
+            while let Some((bag, next_child)) = path.last_mut() {
+                let bag = *bag;
+                match self.direct_children(bag).get(*next_child) {
+                    Some(&(child, _)) => {
+                        *next_child += 1;
+                        match state[child] {
+                            State::New => {
+                                state[child] = State::OnPath;
+                                path.push((child, 0));
+                            }
+                            State::OnPath => {
+                                let start = path.iter().position(|&(b, _)| b == child).unwrap();
+                                let mut cycle: Vec<BagId> =
+                                    path[start..].iter().map(|&(b, _)| b).collect();
+                                cycle.push(child);
+                                out.push(cycle);
+                            }
+                            State::Done => {}
+                        }
+                    }
+                    None => {
+                        state[bag] = State::Done;
+                        path.pop();
+                    }
+                }
+            }
+        }
+        out
+    }
 
-    let goal_bag = "shiny gold";
+    /// Everything wrong with the rules. Unreachable bags are only reported when there's a
+    /// `target` to be unreachable from.
+    pub fn problems(&self, target: Option<BagId>) -> Vec<RuleProblem> {
+        let name = |bag: BagId| self.name(bag).to_string();
+        let mut problems = Vec::new();
 
-    let mut cache = HashMap::new();
+        for bag in 0..self.len() {
+            match self.rule_lines[bag].len() {
+                0 => problems.push(RuleProblem::Undefined {
+                    bag: name(bag),
+                    used_by: name(self.direct_parents(bag)[0].0),
+                }),
+                1 => {}
+                _ => problems.push(RuleProblem::Duplicate {
+                    bag: name(bag),
+                    lines: self.rule_lines[bag].clone(),
+                }),
+            }
+        }
+
+        problems.extend(
+            self.cycles()
+                .into_iter()
+                .map(|cycle| RuleProblem::Cycle(cycle.into_iter().map(name).collect())),
+        );
 
-    fn dfs(
-        rules: &HashMap<String, HashMap<String, usize>>,
-        goal_top: &str,
-        goal_contents: &str,
-        cache: &mut HashMap<String, bool>,
-    ) -> bool {
-        if goal_top == goal_contents {
-            return true;
+        if let Some(target) = target {
+            let mut related = vec![false; self.len()];
+            related[target] = true;
+            for bag in self.containers_of(target) {
+                related[bag] = true;
+            }
+            for bag in self.descendants_in_order(target) {
+                related[bag] = true;
+            }
+
+            problems.extend(
+                (0..self.len())
+                    .filter(|&bag| !related[bag])
+                    .map(|bag| RuleProblem::Unreachable { bag: name(bag) }),
+            );
         }
 
-        let children = rules.get(goal_top).unwrap();
-        if children.contains_key(goal_contents) {
-            return true;
+        problems
+    }
+
+    /// Fails with every fatal problem, one per line. The counting methods give an error rather
+    /// than an answer for a bag which holds a cycle, so it's worth checking this first.
+    pub fn validate(&self) -> Result<(), String> {
+        let fatal: Vec<String> = self
+            .problems(None)
+            .into_iter()
+            .filter(|p| p.is_fatal())
+            .map(|p| p.to_string())
+            .collect();
+
+        if fatal.is_empty() {
+            Ok(())
         } else {
-            for key in children.keys() {
-                if let Some(cached) = cache.get(key) {
-                    if *cached {
-                        return true;
+            Err(fatal.join("\n"))
+        }
+    }
+
+    /// Children come before their parents in the reversed order, so every bag's total is ready
+    /// by the time something holding it needs it; one that isn't must be holding its parent.
+    /// Each bag is summed exactly once, however many paths lead to it.
+    fn count_contents<N: BagCount>(&self, bag: BagId) -> Result<N, String> {
+        let overflow = || self.overflow(bag);
+        let mut totals: HashMap<BagId, N> = HashMap::new();
+
+        for parent in self.descendants_in_order(bag).into_iter().rev() {
+            let mut total = N::from_usize(0);
+            for &(child, count) in self.direct_children(parent) {
+                let inside = totals.get(&child).ok_or_else(|| self.cycle(bag, child))?;
+                let with_child = inside.add(&N::from_usize(1)).ok_or_else(overflow)?;
+                total = total
+                    .add(&with_child.mul_small(count).ok_or_else(overflow)?)
+                    .ok_or_else(overflow)?;
+            }
+            totals.insert(parent, total);
+        }
+
+        Ok(totals
+            .remove(&bag)
+            .expect("The bag itself is always counted"))
+    }
+
+    /// How many bags are inside this one, all the way down (not counting the bag itself), or
+    /// an error if that's too many for a `usize` or the bag ends up inside itself
+    pub fn contents_count(&self, bag: BagId) -> Result<usize, String> {
+        self.count_contents(bag)
+    }
+
+    /// `contents_count`, without a limit
+    pub fn contents_count_big(&self, bag: BagId) -> Result<BigCount, String> {
+        self.count_contents(bag)
+    }
+
+    fn overflow(&self, bag: BagId) -> String {
+        format!("Too many bags inside '{}' to count", self.name(bag))
+    }
+
+    fn cycle(&self, bag: BagId, looped: BagId) -> String {
+        format!(
+            "Can't count the bags inside '{}': '{}' ends up inside itself",
+            self.name(bag),
+            self.name(looped)
+        )
+    }
+
+    /// Every bag reachable from this one, parents before children
+    fn descendants_in_order(&self, bag: BagId) -> Vec<BagId> {
+        let mut seen = vec![false; self.len()];
+        let mut order = Vec::new();
+
+        // Each bag on the way down, with the index of the next child to look at
+        seen[bag] = true;
+        let mut stack: Vec<(BagId, usize)> = vec![(bag, 0)];
+
+        while let Some((current, next_child)) = stack.last_mut() {
+            let current = *current;
+            match self.direct_children(current).get(*next_child) {
+                Some(&(child, _)) => {
+                    *next_child += 1;
+                    if !seen[child] {
+                        seen[child] = true;
+                        stack.push((child, 0));
                     }
-                } else {
-                    let val = dfs(rules, key, goal_contents, cache);
-                    cache.insert(key.to_string(), val);
-                    if val {
-                        return true;
+                }
+                None => {
+                    order.push(current);
+                    stack.pop();
+                }
+            }
+        }
+
+        order.reverse();
+        order
+    }
+
+    /// In the order, every bag comes after everything holding it, so its multiplicity is
+    /// complete by the time it's passed on; an edge pointing back up the order is a cycle.
+    fn multiplicities<N: BagCount>(&self, bag: BagId) -> Result<Vec<(BagId, N)>, String> {
+        let overflow = || self.overflow(bag);
+        let order = self.descendants_in_order(bag);
+        let mut position = vec![usize::MAX; self.len()];
+        for (i, &b) in order.iter().enumerate() {
+            position[b] = i;
+        }
+
+        let mut multiplicity: Vec<Option<N>> = vec![None; self.len()];
+        multiplicity[bag] = Some(N::from_usize(1));
+
+        for &parent in &order {
+            let from_parent = multiplicity[parent]
+                .clone()
+                .expect("Everything holding a bag comes before it");
+            for &(child, count) in self.direct_children(parent) {
+                if position[child] <= position[parent] {
+                    return Err(self.cycle(bag, child));
+                }
+                let extra = from_parent.mul_small(count).ok_or_else(overflow)?;
+                multiplicity[child] = Some(match &multiplicity[child] {
+                    None => extra,
+                    Some(existing) => existing.add(&extra).ok_or_else(overflow)?,
+                });
+            }
+        }
+
+        Ok(multiplicity
+            .into_iter()
+            .enumerate()
+            .filter(|&(id, _)| id != bag)
+            .filter_map(|(id, n)| n.map(|n| (id, n)))
+            .collect())
+    }
+
+    /// `(bag, how many)` for every kind of bag inside this one, all the way down, in id order.
+    /// The counts add up to `contents_count`.
+    pub fn descendant_multiplicities(&self, bag: BagId) -> Result<Vec<(BagId, usize)>, String> {
+        self.multiplicities(bag)
+    }
+
+    /// `descendant_multiplicities`, without a limit
+    pub fn descendant_multiplicities_big(
+        &self,
+        bag: BagId,
+    ) -> Result<Vec<(BagId, BigCount)>, String> {
+        self.multiplicities(bag)
+    }
+
+    /// Every chain of bags leading from `from` down to `to`, both ends included
+    pub fn paths(&self, from: BagId, to: BagId) -> Vec<Vec<BagId>> {
+        if from == to {
+            return vec![vec![from]];
+        }
+
+        // The current path, each bag with the index of the next child to look at
+        let mut on_path = vec![false; self.len()];
+        on_path[from] = true;
+        let mut path: Vec<(BagId, usize)> = vec![(from, 0)];
+        let mut out = Vec::new();
+
+        while let Some((bag, next_child)) = path.last_mut() {
+            let bag = *bag;
+            match self.direct_children(bag).get(*next_child) {
+                Some(&(child, _)) => {
+                    *next_child += 1;
+                    // Never go round a loop
+                    if on_path[child] {
+                        continue;
                     }
+                    if child == to {
+                        let mut found: Vec<BagId> = path.iter().map(|&(b, _)| b).collect();
+                        found.push(child);
+                        out.push(found);
+                    } else {
+                        on_path[child] = true;
+                        path.push((child, 0));
+                    }
+                }
+                None => {
+                    on_path[bag] = false;
+                    path.pop();
                 }
             }
-            false
         }
+        out
+    }
+}
+
+/// The arithmetic bag counting needs; `None` means the result doesn't fit
+trait BagCount: Clone {
+    fn from_usize(n: usize) -> Self;
+    fn add(&self, other: &Self) -> Option<Self>;
+    fn mul_small(&self, n: usize) -> Option<Self>;
+}
+
+impl BagCount for usize {
+    fn from_usize(n: usize) -> usize {
+        n
+    }
+
+    fn add(&self, other: &usize) -> Option<usize> {
+        self.checked_add(*other)
+    }
+
+    fn mul_small(&self, n: usize) -> Option<usize> {
+        self.checked_mul(n)
     }
+}
 
-    rules
-        .keys()
-        .filter(|k| k.as_str() != goal_bag)
-        .filter(|k| dfs(&rules, k, goal_bag, &mut cache))
-        .count()
+/// A count of bags with no upper limit: base 2^32 digits, least significant first, with no
+/// trailing zero digits
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BigCount {
+    digits: Vec<u32>,
+}
+
+impl BigCount {
+    fn trimmed(mut digits: Vec<u32>) -> BigCount {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigCount { digits }
+    }
+
+    /// `None` if it's too big for a `usize`
+    pub fn to_usize(&self) -> Option<usize> {
+        // Anything over four digits is too big for a u128, let alone a usize
+        if self.digits.len() > 4 {
+            return None;
+        }
+        let value = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0u128, |acc, &d| (acc << 32) | d as u128);
+        usize::try_from(value).ok()
+    }
+}
+
+impl BagCount for BigCount {
+    fn from_usize(n: usize) -> BigCount {
+        let n = n as u64;
+        BigCount::trimmed(vec![n as u32, (n >> 32) as u32])
+    }
+
+    fn add(&self, other: &BigCount) -> Option<BigCount> {
+        let len = self.digits.len().max(other.digits.len());
+        let mut digits = Vec::with_capacity(len + 1);
+        let mut carry = 0u64;
+        for i in 0..len {
+            let sum = carry
+                + *self.digits.get(i).unwrap_or(&0) as u64
+                + *other.digits.get(i).unwrap_or(&0) as u64;
+            digits.push(sum as u32);
+            carry = sum >> 32;
+        }
+        digits.push(carry as u32);
+        Some(BigCount::trimmed(digits))
+    }
+
+    fn mul_small(&self, n: usize) -> Option<BigCount> {
+        let mut digits = Vec::with_capacity(self.digits.len() + 2);
+        let mut carry = 0u128;
+        for &d in &self.digits {
+            let product = d as u128 * n as u128 + carry;
+            digits.push(product as u32);
+            carry = product >> 32;
+        }
+        while carry > 0 {
+            digits.push(carry as u32);
+            carry >>= 32;
+        }
+        Some(BigCount::trimmed(digits))
+    }
+}
+
+impl Ord for BigCount {
+    fn cmp(&self, other: &BigCount) -> std::cmp::Ordering {
+        self.digits
+            .len()
+            .cmp(&other.digits.len())
+            .then_with(|| self.digits.iter().rev().cmp(other.digits.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigCount {
+    fn partial_cmp(&self, other: &BigCount) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Peel off nine decimal digits at a time
+        const CHUNK: u64 = 1_000_000_000;
+
+        let mut digits = self.digits.clone();
+        let mut chunks = Vec::new();
+        while !digits.is_empty() {
+            let mut remainder = 0u64;
+            for d in digits.iter_mut().rev() {
+                let value = (remainder << 32) + *d as u64;
+                *d = (value / CHUNK) as u32;
+                remainder = value % CHUNK;
+            }
+            chunks.push(remainder);
+            digits = BigCount::trimmed(digits).digits;
+        }
+
+        let text = match chunks.split_last() {
+            None => "0".to_string(),
+            Some((first, rest)) => rest
+                .iter()
+                .rev()
+                .fold(first.to_string(), |mut text, chunk| {
+                    text.push_str(&format!("{:09}", chunk));
+                    text
+                }),
+        };
+        f.pad(&text)
+    }
+}
+
+/// Which part of the graph an export should pick out
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Highlight {
+    /// The bag and everything inside it
+    From(BagId),
+    /// The bag and everything that can hold it
+    To(BagId),
+}
+
+impl BagGraph {
+    fn highlighted(&self, highlight: Option<Highlight>) -> Vec<bool> {
+        let mut marked = vec![false; self.len()];
+        let bags = match highlight {
+            None => return marked,
+            Some(Highlight::From(bag)) => self.descendants_in_order(bag),
+            Some(Highlight::To(bag)) => {
+                let mut bags = self.containers_of(bag);
+                bags.push(bag);
+                bags
+            }
+        };
+        for bag in bags {
+            marked[bag] = true;
+        }
+        marked
+    }
+
+    /// Every edge as `(parent, child, how many)`, in id then rule order
+    fn edges(&self) -> impl Iterator<Item = (BagId, BagId, usize)> + '_ {
+        (0..self.len()).flat_map(move |parent| {
+            self.direct_children(parent)
+                .iter()
+                .map(move |&(child, count)| (parent, child, count))
+        })
+    }
+
+    /// Graphviz, with each edge labelled by its count
+    pub fn to_dot(&self, highlight: Option<Highlight>) -> String {
+        let marked = self.highlighted(highlight);
+        let quote = |bag: BagId| format!("\"{}\"", self.name(bag).replace('"', "\\\""));
+
+        let mut lines = vec!["digraph bags {".to_string()];
+        for (bag, &is_marked) in marked.iter().enumerate() {
+            let style = if is_marked {
+                " [style=filled, fillcolor=gold]"
+            } else {
+                ""
+            };
+            lines.push(format!("    {}{};", quote(bag), style));
+        }
+        for (parent, child, count) in self.edges() {
+            let style = if marked[parent] && marked[child] {
+                ", color=orange, penwidth=2"
+            } else {
+                ""
+            };
+            lines.push(format!(
+                "    {} -> {} [label=\"{}\"{}];",
+                quote(parent),
+                quote(child),
+                count,
+                style
+            ));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    /// A Mermaid flowchart, with each edge labelled by its count
+    pub fn to_mermaid(&self, highlight: Option<Highlight>) -> String {
+        let marked = self.highlighted(highlight);
+
+        let mut lines = vec!["graph TD".to_string()];
+        for bag in 0..self.len() {
+            lines.push(format!(
+                "    b{}[\"{}\"]",
+                bag,
+                self.name(bag).replace('"', "#quot;")
+            ));
+        }
+
+        let mut marked_edges = Vec::new();
+        for (i, (parent, child, count)) in self.edges().enumerate() {
+            lines.push(format!("    b{} -->|{}| b{}", parent, count, child));
+            if marked[parent] && marked[child] {
+                marked_edges.push(i.to_string());
+            }
+        }
+
+        let marked_bags: Vec<String> = (0..self.len())
+            .filter(|&bag| marked[bag])
+            .map(|bag| format!("b{}", bag))
+            .collect();
+        if !marked_bags.is_empty() {
+            lines.push("    classDef highlight fill:#fd0,stroke:#f90".to_string());
+            lines.push(format!("    class {} highlight", marked_bags.join(",")));
+        }
+        if !marked_edges.is_empty() {
+            lines.push(format!(
+                "    linkStyle {} stroke:#f90,stroke-width:2px",
+                marked_edges.join(",")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+const GOAL_BAG: &str = "shiny gold";
+
+fn checked_graph(input: &str) -> Result<BagGraph, String> {
+    let graph = BagGraph::parse(input)?;
+    graph.validate()?;
+    Ok(graph)
+}
+
+fn run_7a_with_input(input: &str) -> usize {
+    let graph = checked_graph(input).unwrap();
+    graph.containers_of(graph.lookup(GOAL_BAG).unwrap()).len()
 }
 
 pub fn run_7a() -> usize {
@@ -139,38 +776,133 @@ pub fn run_7a() -> usize {
 }
 
 fn run_7b_with_input(input: &str) -> usize {
-    let rules = parse::parse(input);
+    let graph = checked_graph(input).unwrap();
+    graph
+        .contents_count(graph.lookup(GOAL_BAG).unwrap())
+        .unwrap()
+}
 
-    let goal_bag = "shiny gold";
+pub fn run_7b() -> usize {
+    run_7b_with_input(INPUT)
+}
 
-    let mut cache = HashMap::new();
+/// CLI entry point: `7bag <colour> [--big] [--input <file>]`, e.g. `7bag dark olive`, listing
+/// the bags which can hold it and everything it has to hold. Counts too big for a `usize` are
+/// an error unless `--big` is given.
+pub fn run_7bag(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 7bag <colour> [--big] [--input <file>]";
 
-    fn dfs(
-        rules: &HashMap<String, HashMap<String, usize>>,
-        top_bag: &str,
-        cache: &mut HashMap<String, usize>,
-    ) -> usize {
-        1 + rules
-            .get(top_bag)
-            .unwrap()
-            .iter()
-            .map(|(kind, count)| {
-                if let Some(val) = cache.get(top_bag) {
-                    return *val;
-                }
-                let val = count * dfs(rules, kind, cache);
-                cache.insert(kind.to_string(), val);
-                val
-            })
-            .sum::<usize>()
+    let (input, mut colour) = read_input_arg(args, INPUT, usage)?;
+    let big = colour.iter().any(|arg| arg == "--big");
+    colour.retain(|arg| arg != "--big");
+    if colour.is_empty() {
+        return Err(usage.to_string());
     }
 
-    // You don't count the top bag
-    dfs(&rules, goal_bag, &mut cache) - 1
+    let graph = checked_graph(&input)?;
+    let bag = graph.lookup(&colour.join(" "))?;
+
+    let mut containers: Vec<&str> = graph
+        .containers_of(bag)
+        .into_iter()
+        .map(|b| graph.name(b))
+        .collect();
+    containers.sort_unstable();
+
+    let (total, mut contents) = if big {
+        (
+            graph.contents_count_big(bag)?,
+            graph.descendant_multiplicities_big(bag)?,
+        )
+    } else {
+        let small = graph.descendant_multiplicities(bag)?;
+        (
+            BigCount::from_usize(graph.contents_count(bag)?),
+            small
+                .into_iter()
+                .map(|(b, n)| (b, BigCount::from_usize(n)))
+                .collect(),
+        )
+    };
+    contents.sort_by(|a, b| b.1.cmp(&a.1).then(graph.name(a.0).cmp(graph.name(b.0))));
+
+    let mut lines = vec![
+        format!(
+            "{} can go inside {} bags: {}",
+            graph.name(bag),
+            containers.len(),
+            containers.join(", ")
+        ),
+        format!("it must contain {} bags:", total),
+    ];
+    lines.extend(
+        contents
+            .into_iter()
+            .map(|(b, n)| format!("{:>8} {}", n, graph.name(b))),
+    );
+    Ok(lines.join("\n"))
 }
 
-pub fn run_7b() -> usize {
-    run_7b_with_input(INPUT)
+/// CLI entry point: `7check [<colour>] [--input <file>]`, listing every problem with the
+/// rules, including bags unrelated to the given colour
+pub fn run_7check(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 7check [<colour>] [--input <file>]";
+
+    let (input, colour) = read_input_arg(args, INPUT, usage)?;
+    let graph = BagGraph::parse(&input)?;
+    let target = match colour.as_slice() {
+        [] => None,
+        _ => Some(graph.lookup(&colour.join(" "))?),
+    };
+
+    let problems = graph.problems(target);
+    let mut lines: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+    lines.push(format!(
+        "{} rules, {} problems ({} fatal)",
+        graph.rule_count(),
+        problems.len(),
+        problems.iter().filter(|p| p.is_fatal()).count()
+    ));
+    Ok(lines.join("\n"))
+}
+
+/// CLI entry point: `7export dot|mermaid <output> [--from <colour> | --to <colour>]
+/// [--input <file>]`, writing the rules out as a graph, optionally highlighting everything
+/// inside (`--from`) or around (`--to`) one bag. Rules with cycles or undefined bags are
+/// drawn as they are, which helps with tracking those down.
+pub fn run_7export(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 7export dot|mermaid <output> [--from <colour> | --to <colour>] \
+                 [--input <file>]";
+
+    let (input, rest) = read_input_arg(args, INPUT, usage)?;
+    let (format, output, rest) = match rest.as_slice() {
+        [format, output, rest @ ..] => (format.as_str(), output, rest),
+        _ => return Err(usage.to_string()),
+    };
+
+    let graph = BagGraph::parse(&input)?;
+    let highlight = match rest {
+        [] => None,
+        [flag, colour @ ..] if !colour.is_empty() => {
+            let bag = graph.lookup(&colour.join(" "))?;
+            match flag.as_str() {
+                "--from" => Some(Highlight::From(bag)),
+                "--to" => Some(Highlight::To(bag)),
+                _ => return Err(usage.to_string()),
+            }
+        }
+        _ => return Err(usage.to_string()),
+    };
+
+    let text = match format {
+        "dot" => graph.to_dot(highlight),
+        "mermaid" => graph.to_mermaid(highlight),
+        _ => return Err(usage.to_string()),
+    };
+    std::fs::write(output, text + "\n")
+        .map_err(|e| format!("Could not write {}: {}", output, e))?;
+
+    Ok(format!("Wrote {} bags to {}", graph.len(), output))
 }
 
 #[cfg(test)]
@@ -209,4 +941,287 @@ dark violet bags contain no other bags.";
     fn sample_7b_2() {
         assert_eq!(run_7b_with_input(SAMPLE_INPUT_2), 126);
     }
+
+    #[test]
+    fn bag_graph() {
+        let graph = BagGraph::parse(SAMPLE_INPUT).unwrap();
+        let id = |name| graph.id(name).unwrap();
+        let names = |ids: Vec<BagId>| ids.into_iter().map(|b| graph.name(b)).collect::<Vec<_>>();
+
+        assert_eq!(graph.len(), 9);
+        assert_eq!(
+            graph.direct_children(id("shiny gold")),
+            &[(id("dark olive"), 1), (id("vibrant plum"), 2)]
+        );
+        assert_eq!(
+            names(graph.containers_of(id("shiny gold"))),
+            vec!["light red", "bright white", "muted yellow", "dark orange"]
+        );
+        assert_eq!(graph.contents_count(id("dark olive")), Ok(7));
+        assert_eq!(graph.contents_count(id("faded blue")), Ok(0));
+
+        let paths = graph.paths(id("light red"), id("shiny gold"));
+        assert_eq!(paths.len(), 2);
+        assert_eq!(
+            names(paths[0].clone()),
+            vec!["light red", "bright white", "shiny gold"]
+        );
+        assert!(graph.paths(id("shiny gold"), id("light red")).is_empty());
+
+        assert_eq!(
+            graph.descendant_multiplicities(id("shiny gold")),
+            Ok(vec![
+                (id("faded blue"), 13),
+                (id("dark olive"), 1),
+                (id("vibrant plum"), 2),
+                (id("dotted black"), 16)
+            ])
+        );
+        assert!(graph
+            .lookup("plaid green")
+            .unwrap_err()
+            .contains("plaid green"));
+    }
+
+    #[test]
+    fn rule_problems() {
+        let input = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+shiny gold bags contain 2 light red bags.
+muted yellow bags contain 3 faded blue bags.
+bright white bags contain no other bags.
+dotted black bags contain no other bags.";
+        let graph = BagGraph::parse(input).unwrap();
+        let problems = graph.problems(graph.id("faded blue"));
+
+        assert_eq!(
+            problems,
+            vec![
+                RuleProblem::Duplicate {
+                    bag: "bright white".to_string(),
+                    lines: vec![2, 5]
+                },
+                RuleProblem::Undefined {
+                    bag: "faded blue".to_string(),
+                    used_by: "muted yellow".to_string()
+                },
+                RuleProblem::Cycle(vec![
+                    "light red".to_string(),
+                    "bright white".to_string(),
+                    "shiny gold".to_string(),
+                    "light red".to_string()
+                ]),
+                RuleProblem::Unreachable {
+                    bag: "dotted black".to_string()
+                },
+            ]
+        );
+        assert_eq!(graph.validate().unwrap_err().lines().count(), 3);
+
+        // Counting through the cycle is an error rather than a panic
+        let red = graph.id("light red").unwrap();
+        assert_eq!(
+            graph.contents_count(red).unwrap_err(),
+            "Can't count the bags inside 'light red': 'light red' ends up inside itself"
+        );
+        assert!(graph.contents_count_big(red).is_err());
+        assert!(graph.descendant_multiplicities(red).is_err());
+        assert!(graph.descendant_multiplicities_big(red).is_err());
+        let yellow = graph.id("muted yellow").unwrap();
+        assert_eq!(graph.contents_count(yellow), Ok(3));
+
+        assert_eq!(BagGraph::parse(SAMPLE_INPUT).unwrap().validate(), Ok(()));
+    }
+
+    /// Bag colours are letters only, so number the levels `a`, `b`, ..., `ba`, ...
+    fn level(mut n: usize) -> String {
+        let mut out = vec![(b'a' + (n % 26) as u8) as char];
+        n /= 26;
+        while n > 0 {
+            out.push((b'a' + (n % 26) as u8) as char);
+            n /= 26;
+        }
+        out.iter().rev().collect()
+    }
+
+    #[test]
+    fn contents_count_diamonds() {
+        // Both B and C hold D, at different multiplicities
+        let input = "bright aqua bags contain 2 bright blue bags, 3 bright cyan bags.
+bright blue bags contain 5 dark drab bags.
+bright cyan bags contain 1 dark drab bag.
+dark drab bags contain 7 empty end bags.
+empty end bags contain no other bags.";
+        let graph = BagGraph::parse(input).unwrap();
+        let id = |name| graph.id(name).unwrap();
+
+        assert_eq!(graph.contents_count(id("dark drab")), Ok(7));
+        assert_eq!(graph.contents_count(id("bright blue")), Ok(40));
+        assert_eq!(graph.contents_count(id("bright cyan")), Ok(8));
+        assert_eq!(graph.contents_count(id("bright aqua")), Ok(2 * 41 + 3 * 9));
+
+        // 40 diamonds stacked on top of each other: walking every path would take 2^40 steps
+        let levels = 40;
+        let mut rules = Vec::new();
+        for n in 1..=levels {
+            let (this, below) = (level(n), level(n - 1));
+            rules.push(format!(
+                "top {} bags contain 1 left {} bag, 1 right {} bag.",
+                this, this, this
+            ));
+            rules.push(format!("left {} bags contain 1 top {} bag.", this, below));
+            rules.push(format!("right {} bags contain 1 top {} bag.", this, below));
+        }
+        rules.push("top a bags contain no other bags.".to_string());
+        let graph = BagGraph::parse(&rules.join("\n")).unwrap();
+        let top = graph.id(&format!("top {}", level(levels))).unwrap();
+
+        assert_eq!(graph.validate(), Ok(()));
+        assert_eq!(graph.contents_count(top), Ok(4 * (1 << levels) - 4));
+        assert_eq!(
+            graph
+                .descendant_multiplicities(top)
+                .unwrap()
+                .iter()
+                .map(|&(_, n)| n)
+                .sum::<usize>(),
+            graph.contents_count(top).unwrap()
+        );
+    }
+
+    #[test]
+    fn contents_count_deep() {
+        let depth = 100_000;
+        let mut rules: Vec<String> = (0..depth)
+            .map(|i| {
+                format!(
+                    "deep {} bags contain 1 deep {} bag.",
+                    level(i),
+                    level(i + 1)
+                )
+            })
+            .collect();
+        rules.push(format!("deep {} bags contain no other bags.", level(depth)));
+        let graph = BagGraph::parse(&rules.join("\n")).unwrap();
+
+        assert_eq!(graph.contents_count(graph.id("deep a").unwrap()), Ok(depth));
+        assert_eq!(graph.validate(), Ok(()));
+        let bottom = graph.id(&format!("deep {}", level(depth))).unwrap();
+        let paths = graph.paths(graph.id("deep a").unwrap(), bottom);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].len(), depth + 1);
+
+        // Closing the chain into a loop makes one very long cycle
+        rules.pop();
+        rules.push(format!("deep {} bags contain 1 deep a bag.", level(depth)));
+        let graph = BagGraph::parse(&rules.join("\n")).unwrap();
+        match graph.problems(None).as_slice() {
+            [RuleProblem::Cycle(cycle)] => assert_eq!(cycle.len(), depth + 2),
+            other => panic!("Expected one cycle, got {:?}", other.len()),
+        }
+        assert!(graph.contents_count(graph.id("deep a").unwrap()).is_err());
+    }
+
+    #[test]
+    fn graph_export() {
+        let graph = BagGraph::parse(
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain no other bags.
+shiny gold bags contain no other bags.",
+        )
+        .unwrap();
+        let gold = graph.id("shiny gold").unwrap();
+
+        assert_eq!(
+            graph.to_dot(Some(Highlight::To(gold))),
+            "digraph bags {
+    \"light red\" [style=filled, fillcolor=gold];
+    \"bright white\" [style=filled, fillcolor=gold];
+    \"muted yellow\";
+    \"shiny gold\" [style=filled, fillcolor=gold];
+    \"light red\" -> \"bright white\" [label=\"1\", color=orange, penwidth=2];
+    \"light red\" -> \"muted yellow\" [label=\"2\"];
+    \"bright white\" -> \"shiny gold\" [label=\"1\", color=orange, penwidth=2];
+}"
+        );
+
+        assert_eq!(
+            graph.to_mermaid(Some(Highlight::From(gold))),
+            "graph TD
+    b0[\"light red\"]
+    b1[\"bright white\"]
+    b2[\"muted yellow\"]
+    b3[\"shiny gold\"]
+    b0 -->|1| b1
+    b0 -->|2| b2
+    b1 -->|1| b3
+    classDef highlight fill:#fd0,stroke:#f90
+    class b3 highlight"
+        );
+        assert!(!graph.to_mermaid(None).contains("highlight"));
+
+        // Broken rules can still be drawn
+        let broken = BagGraph::parse(
+            "light red bags contain 1 bright white bag.
+bright white bags contain 2 light red bags, 3 faded blue bags.",
+        )
+        .unwrap();
+        assert!(broken.validate().is_err());
+        assert_eq!((broken.len(), broken.rule_count()), (3, 2));
+        let red = broken.id("light red").unwrap();
+        assert_eq!(
+            broken.to_dot(Some(Highlight::From(red))),
+            "digraph bags {
+    \"light red\" [style=filled, fillcolor=gold];
+    \"bright white\" [style=filled, fillcolor=gold];
+    \"faded blue\" [style=filled, fillcolor=gold];
+    \"light red\" -> \"bright white\" [label=\"1\", color=orange, penwidth=2];
+    \"bright white\" -> \"light red\" [label=\"2\", color=orange, penwidth=2];
+    \"bright white\" -> \"faded blue\" [label=\"3\", color=orange, penwidth=2];
+}"
+        );
+    }
+
+    #[test]
+    fn big_counts() {
+        // Each level holds two of the next, so the top holds 2 + 4 + ... + 2^100 bags
+        let depth = 100;
+        let mut rules: Vec<String> = (0..depth)
+            .map(|i| format!("big {} bags contain 2 big {} bags.", level(i), level(i + 1)))
+            .collect();
+        rules.push(format!("big {} bags contain no other bags.", level(depth)));
+        let graph = BagGraph::parse(&rules.join("\n")).unwrap();
+        let top = graph.id("big a").unwrap();
+
+        assert!(graph.contents_count(top).is_err());
+        assert!(graph.descendant_multiplicities(top).is_err());
+        assert_eq!(
+            graph.contents_count_big(top).unwrap().to_string(),
+            "2535301200456458802993406410750"
+        );
+        let last = graph
+            .descendant_multiplicities_big(top)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(last.1.to_string(), "1267650600228229401496703205376");
+        assert_eq!(last.1.to_usize(), None);
+
+        let sample = BagGraph::parse(SAMPLE_INPUT_2).unwrap();
+        let gold = sample.id("shiny gold").unwrap();
+        assert_eq!(
+            sample.contents_count_big(gold).unwrap().to_usize(),
+            Some(126)
+        );
+        assert_eq!(format!("{:>3}", BigCount::default()), "  0");
+        assert_eq!(
+            BigCount::from_usize(usize::MAX).to_usize(),
+            Some(usize::MAX)
+        );
+        assert_eq!(
+            BigCount::from_usize(1_000_000_000).to_string(),
+            "1000000000"
+        );
+    }
 }
@@ -1,14 +1,41 @@
-use nom::lib::std::collections::HashSet;
-
 const INPUT: &str = include_str!("input/8.txt");
 
+pub mod debug;
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::read_input_arg;
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-enum Op {
+pub enum Op {
     Nop(isize),
     Acc(i64),
     Jmp(isize),
 }
 
+impl Op {
+    /// `nop` and `jmp` swapped, keeping the argument; `acc` can't be flipped
+    pub fn flipped(self) -> Option<Op> {
+        match self {
+            Op::Nop(amt) => Some(Op::Jmp(amt)),
+            Op::Jmp(amt) => Some(Op::Nop(amt)),
+            Op::Acc(_) => None,
+        }
+    }
+}
+
+/// The same form the puzzle uses, e.g. `jmp -4`
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Nop(amt) => write!(f, "nop {:+}", amt),
+            Op::Acc(amt) => write!(f, "acc {:+}", amt),
+            Op::Jmp(amt) => write!(f, "jmp {:+}", amt),
+        }
+    }
+}
+
 mod parse {
 
     use nom::{
@@ -21,7 +48,7 @@ mod parse {
         IResult,
     };
 
-    use crate::lib::parse_usize;
+    use crate::parse_usize;
 
     use super::Op;
 
@@ -49,40 +76,221 @@ mod parse {
         ))(input)
     }
 
-    pub(super) fn parse(input: &str) -> Vec<Op> {
+    pub(super) fn parse(input: &str) -> Result<Vec<Op>, String> {
         let (_, ops) =
-            map(tuple((separated_list1(newline, parse_op), eof)), |(v, _)| v)(input).unwrap();
+            map(tuple((separated_list1(newline, parse_op), eof)), |(v, _)| v)(input.trim_end())
+                .map_err(|e| format!("Could not parse program: {:?}", e))?;
 
-        ops
+        Ok(ops)
     }
 }
 
-fn run_8a_with_input(input: &str) -> i64 {
-    let ops = parse::parse(input);
+pub fn parse_program(input: &str) -> Result<Vec<Op>, String> {
+    parse::parse(input)
+}
 
-    let mut acc = 0;
-    let mut ip = 0;
-    let mut seen = HashSet::new();
+/// How a program run ended
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Outcome {
+    /// Ran off the end of the program, exactly one past the last instruction
+    Terminated(i64),
+    /// Was about to run the instruction at `ip` a second time
+    Looped { ip: usize, acc: i64 },
+    /// Jumped somewhere other than into the program or just past its end
+    OutOfBounds { ip: isize },
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Status {
+    Running,
+    Finished(Outcome),
+}
+
+/// One executed instruction, as recorded in trace mode
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TraceEntry {
+    pub ip: usize,
+    pub op: Op,
+    pub acc_before: i64,
+    pub acc_after: i64,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5}  {:<10} acc {} -> {}",
+            self.ip,
+            self.op.to_string(),
+            self.acc_before,
+            self.acc_after
+        )
+    }
+}
+
+/// The handheld's boot code interpreter
+#[derive(Clone, Debug)]
+pub struct Machine {
+    program: Vec<Op>,
+    ip: isize,
+    acc: i64,
+    steps: usize,
+    /// Which instructions have already run, for spotting loops
+    visited: Vec<bool>,
+    /// Whether coming back to an instruction ends the run as `Looped`
+    detect_loops: bool,
+    tracing: bool,
+    trace: Vec<TraceEntry>,
+    status: Status,
+}
+
+impl Machine {
+    pub fn new(program: Vec<Op>) -> Machine {
+        let visited = vec![false; program.len()];
+        let mut machine = Machine {
+            program,
+            ip: 0,
+            acc: 0,
+            steps: 0,
+            visited,
+            detect_loops: true,
+            tracing: false,
+            trace: Vec::new(),
+            status: Status::Running,
+        };
+        machine.check_ip();
+        machine
+    }
+
+    pub fn program(&self) -> &[Op] {
+        &self.program
+    }
 
-    loop {
-        if seen.contains(&ip) {
-            return acc;
+    pub fn ip(&self) -> isize {
+        self.ip
+    }
+
+    pub fn acc(&self) -> i64 {
+        self.acc
+    }
+
+    /// How many instructions have run
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// The instruction about to run, if there is one
+    pub fn current(&self) -> Option<Op> {
+        match self.status {
+            Status::Running => Some(self.program[self.ip as usize]),
+            Status::Finished(_) => None,
         }
-        seen.insert(ip);
+    }
 
-        let op = ops[ip as usize];
-        match op {
-            Op::Acc(i) => {
-                acc += i;
-            }
-            Op::Nop(_) => {
-                // nothing
+    /// Whether the instruction about to run has run before. Only ever true with loop
+    /// detection off, since otherwise the run would have finished.
+    pub fn about_to_revisit(&self) -> bool {
+        self.status == Status::Running && self.visited[self.ip as usize]
+    }
+
+    /// With loop detection off, a looping program keeps going until `run_until`'s limit
+    pub fn set_loop_detection(&mut self, on: bool) {
+        self.detect_loops = on;
+        self.check_ip();
+    }
+
+    /// While tracing is on, every instruction run is added to `trace`
+    pub fn set_tracing(&mut self, on: bool) {
+        self.tracing = on;
+    }
+
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+
+    /// Hands over everything traced so far, leaving the trace empty
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        std::mem::take(&mut self.trace)
+    }
+
+    /// Finishes the run if `ip` has left the program or come back to somewhere it's been
+    fn check_ip(&mut self) {
+        let len = self.program.len() as isize;
+        self.status = if self.ip == len {
+            Status::Finished(Outcome::Terminated(self.acc))
+        } else if self.ip < 0 || self.ip > len {
+            Status::Finished(Outcome::OutOfBounds { ip: self.ip })
+        } else if self.detect_loops && self.visited[self.ip as usize] {
+            Status::Finished(Outcome::Looped {
+                ip: self.ip as usize,
+                acc: self.acc,
+            })
+        } else {
+            Status::Running
+        };
+    }
+
+    /// Runs one instruction; does nothing once the run has finished
+    pub fn step(&mut self) -> Status {
+        if let Some(op) = self.current() {
+            let ip = self.ip as usize;
+            let acc_before = self.acc;
+
+            self.visited[ip] = true;
+            match op {
+                Op::Acc(i) => {
+                    self.acc += i;
+                    self.ip += 1;
+                }
+                Op::Nop(_) => self.ip += 1,
+                Op::Jmp(amt) => self.ip += amt,
             }
-            Op::Jmp(amt) => {
-                ip += amt - 1;
+            self.steps += 1;
+
+            if self.tracing {
+                self.trace.push(TraceEntry {
+                    ip,
+                    op,
+                    acc_before,
+                    acc_after: self.acc,
+                });
             }
+            self.check_ip();
+        }
+        self.status
+    }
+
+    /// Runs until the program halts or loops, or `limit` more instructions have run. Still
+    /// `Running` means the limit was hit.
+    pub fn run_until(&mut self, limit: Option<usize>) -> Status {
+        let mut remaining = limit.unwrap_or(usize::MAX);
+        while self.status == Status::Running && remaining > 0 {
+            self.step();
+            remaining -= 1;
         }
-        ip += 1;
+        self.status
+    }
+
+    /// Runs until the program halts or loops, which it always does eventually (as long as
+    /// loop detection is on)
+    pub fn run(&mut self) -> Outcome {
+        match self.run_until(None) {
+            Status::Finished(outcome) => outcome,
+            Status::Running => unreachable!("Ran for usize::MAX steps"),
+        }
+    }
+}
+
+fn run_8a_with_input(input: &str) -> i64 {
+    let program = parse_program(input).unwrap();
+
+    match Machine::new(program).run() {
+        Outcome::Looped { acc, .. } => acc,
+        other => panic!("Program didn't loop: {:?}", other),
     }
 }
 
@@ -90,72 +298,159 @@ pub fn run_8a() -> i64 {
     run_8a_with_input(INPUT)
 }
 
-fn run_8b_with_input(input: &str) -> i64 {
-    fn terminates(ops: &[Op]) -> Option<i64> {
-        let mut acc = 0;
-        let mut ip = 0;
-        let mut seen = HashSet::new();
+/// The one instruction to swap so the program terminates
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Repair {
+    pub ip: usize,
+    pub original: Op,
+    pub replacement: Op,
+    /// The accumulator once the repaired program terminates
+    pub acc: i64,
+}
 
-        let ilen = ops.len() as isize;
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {} -> {} (accumulator {})",
+            self.ip + 1,
+            self.original,
+            self.replacement,
+            self.acc
+        )
+    }
+}
 
-        while ip >= 0 && ip < ilen {
-            if seen.contains(&ip) {
-                return None;
-            }
-            seen.insert(ip);
+/// Where the instruction at `ip` goes next, if it stays inside the program (or exactly at its
+/// end)
+fn successor(program: &[Op], ip: usize, op: Op) -> Option<usize> {
+    let next = match op {
+        Op::Jmp(amt) => ip as isize + amt,
+        Op::Nop(_) | Op::Acc(_) => ip as isize + 1,
+    };
+    if (0..=program.len() as isize).contains(&next) {
+        Some(next as usize)
+    } else {
+        None
+    }
+}
 
-            let op = ops[ip as usize];
-            match op {
-                Op::Acc(i) => {
-                    acc += i;
-                }
-                Op::Nop(_) => {
-                    // nothing
-                }
-                Op::Jmp(amt) => {
-                    ip += amt - 1;
-                }
-            }
-            ip += 1;
+/// For each instruction, plus the end of the program, whether running from there terminates.
+/// Works backwards from the end along reversed edges, so each instruction is looked at once.
+fn reaches_end(program: &[Op]) -> Vec<bool> {
+    let mut predecessors = vec![Vec::new(); program.len() + 1];
+    for (ip, &op) in program.iter().enumerate() {
+        if let Some(next) = successor(program, ip, op) {
+            predecessors[next].push(ip);
         }
+    }
 
-        Some(acc)
+    let mut reaches = vec![false; program.len() + 1];
+    reaches[program.len()] = true;
+    let mut queue = VecDeque::from(vec![program.len()]);
+    while let Some(ip) = queue.pop_front() {
+        for &pred in &predecessors[ip] {
+            if !reaches[pred] {
+                reaches[pred] = true;
+                queue.push_back(pred);
+            }
+        }
     }
+    reaches
+}
 
-    let mut ops = parse::parse(input);
+/// Finds the first instruction on the program's path which, swapped between `nop` and `jmp`,
+/// lands somewhere that terminates. Everything before the swap runs as it did, and nothing
+/// after it can lead back (or the original program would have terminated), so that's enough.
+/// Linear in the length of the program.
+pub fn find_repair(program: &[Op]) -> Result<Repair, String> {
+    let reaches = reaches_end(program);
+    if reaches[0] {
+        return Err("The program already terminates".to_string());
+    }
 
-    for i in 0..ops.len() {
-        let op = ops[i];
-        match op {
-            Op::Acc(_) => {}
-            Op::Nop(amt) => {
-                ops[i] = Op::Jmp(amt);
-                if let Some(v) = terminates(&ops) {
-                    return v;
-                }
-                ops[i] = Op::Nop(amt);
-            }
-            Op::Jmp(amt) => {
-                ops[i] = Op::Nop(amt);
-                if let Some(v) = terminates(&ops) {
-                    return v;
-                }
-                ops[i] = Op::Jmp(amt);
+    let mut machine = Machine::new(program.to_vec());
+    while let Some(op) = machine.current() {
+        let ip = machine.ip() as usize;
+        if let Some(replacement) = op.flipped() {
+            if successor(program, ip, replacement).is_some_and(|next| reaches[next]) {
+                let mut repaired = program.to_vec();
+                repaired[ip] = replacement;
+                return match Machine::new(repaired).run() {
+                    Outcome::Terminated(acc) => Ok(Repair {
+                        ip,
+                        original: op,
+                        replacement,
+                        acc,
+                    }),
+                    other => unreachable!("Repaired program didn't terminate: {:?}", other),
+                };
             }
         }
+        machine.step();
     }
 
-    panic!("No valid change found :(");
+    Err("No single swap makes the program terminate".to_string())
+}
+
+fn run_8b_with_input(input: &str) -> i64 {
+    let program = parse_program(input).unwrap();
+    find_repair(&program).unwrap().acc
 }
 
 pub fn run_8b() -> i64 {
     run_8b_with_input(INPUT)
 }
 
+/// CLI entry point: `8repair [--input <file>]`, showing which instruction to swap and the
+/// accumulator afterwards
+pub fn run_8repair(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 8repair [--input <file>]";
+
+    let (input, rest) = read_input_arg(args, INPUT, usage)?;
+    if !rest.is_empty() {
+        return Err(usage.to_string());
+    }
+
+    let program = parse_program(&input)?;
+    Ok(find_repair(&program)?.to_string())
+}
+
+/// CLI entry point: `8debug [--trace] [--input <file>]`. With `--trace`, runs the program
+/// printing every instruction; otherwise reads debugger commands from stdin (`help` lists
+/// them).
+pub fn run_8debug(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: 8debug [--trace] [--input <file>]";
+
+    let (input, rest) = read_input_arg(args, INPUT, usage)?;
+    let trace = match rest.as_slice() {
+        [] => false,
+        [flag] if flag == "--trace" => true,
+        _ => return Err(usage.to_string()),
+    };
+    let mut machine = Machine::new(parse_program(&input)?);
+
+    if trace {
+        machine.set_tracing(true);
+        let outcome = machine.run();
+        let mut lines: Vec<String> = machine.trace().iter().map(|e| e.to_string()).collect();
+        lines.push(format!("{:?}", outcome));
+        return Ok(lines.join("\n"));
+    }
+
+    let mut debugger = debug::Debugger::new(machine);
+    let stdout = std::io::stdout();
+    debug::session(&mut debugger, std::io::stdin().lock(), &mut stdout.lock())
+        .map_err(|e| format!("Debugger I/O failed: {}", e))?;
+    Ok(format!("{:?}", debugger.machine().status()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::XorShift;
+
     const SAMPLE: &str = "nop +0
 acc +1
 jmp +4
@@ -175,4 +470,71 @@ acc +6";
     fn sample_8b() {
         assert_eq!(run_8b_with_input(SAMPLE), 8);
     }
+
+    #[test]
+    fn machine() {
+        let mut machine = Machine::new(parse_program(SAMPLE).unwrap());
+        assert_eq!(machine.current(), Some(Op::Nop(0)));
+        assert_eq!(machine.step(), Status::Running);
+        assert_eq!((machine.ip(), machine.acc()), (1, 0));
+        assert_eq!(machine.run_until(Some(2)), Status::Running);
+        assert_eq!((machine.ip(), machine.acc(), machine.steps()), (6, 1, 3));
+        assert_eq!(machine.run(), Outcome::Looped { ip: 1, acc: 5 });
+        assert_eq!(
+            machine.step(),
+            Status::Finished(Outcome::Looped { ip: 1, acc: 5 })
+        );
+
+        let mut fixed = parse_program(SAMPLE).unwrap();
+        fixed[7] = fixed[7].flipped().unwrap();
+        assert_eq!(Machine::new(fixed).run(), Outcome::Terminated(8));
+
+        let escapes = parse_program("acc +1\njmp +5").unwrap();
+        assert_eq!(Machine::new(escapes).run(), Outcome::OutOfBounds { ip: 6 });
+        assert_eq!(Machine::new(Vec::new()).run(), Outcome::Terminated(0));
+    }
+
+    #[test]
+    fn repair() {
+        let program = parse_program(SAMPLE).unwrap();
+        let repair = find_repair(&program).unwrap();
+        assert_eq!(
+            repair.to_string(),
+            "line 8: jmp -4 -> nop -4 (accumulator 8)"
+        );
+
+        let fine = parse_program("nop +0\nacc +1").unwrap();
+        assert!(find_repair(&fine).is_err());
+        let hopeless = parse_program("acc +1\njmp +0\njmp -1").unwrap();
+        assert!(find_repair(&hopeless).is_err());
+    }
+
+    #[test]
+    fn repair_large_program() {
+        // Mostly forward, except one jump back three quarters of the way along, so it loops;
+        // turning that jump into a nop is one fix, though maybe not the first one found
+        let len = 100_000;
+        let mut rng = XorShift::new(8);
+        let mut program: Vec<Op> = (0..len)
+            .map(|ip| match rng.below(3) {
+                0 => Op::Acc(rng.between(0, 200) as i64 - 100),
+                1 => Op::Nop(rng.between(0, 2 * len) as isize - len as isize),
+                _ => Op::Jmp(rng.between(1, 3.min(len - ip)) as isize),
+            })
+            .collect();
+        // Nothing can jump over it
+        let back = len * 3 / 4;
+        program[back - 2] = Op::Acc(1);
+        program[back - 1] = Op::Acc(1);
+        program[back] = Op::Jmp(-(rng.between(1, back) as isize));
+        assert!(matches!(
+            Machine::new(program.clone()).run(),
+            Outcome::Looped { .. }
+        ));
+
+        let repair = find_repair(&program).unwrap();
+        assert_eq!(program[repair.ip], repair.original);
+        program[repair.ip] = repair.replacement;
+        assert_eq!(Machine::new(program).run(), Outcome::Terminated(repair.acc));
+    }
 }
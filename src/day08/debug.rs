@@ -0,0 +1,341 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use super::{Machine, Outcome, Status, TraceEntry};
+
+/// Somewhere for the debugger to stop
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Breakpoint {
+    /// About to run the instruction at this `ip`
+    Ip(usize),
+    /// The accumulator has just become this value
+    Acc(i64),
+    /// About to run an instruction for the second time
+    Revisit,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Ip(ip) => write!(f, "ip {}", ip),
+            Breakpoint::Acc(acc) => write!(f, "acc {}", acc),
+            Breakpoint::Revisit => write!(f, "revisit"),
+        }
+    }
+}
+
+/// Why `Debugger::resume` came back
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Stop {
+    Breakpoint(Breakpoint),
+    Finished(Outcome),
+    /// Ran out of steps before anything else happened
+    Limit,
+}
+
+/// A machine with breakpoints. Loop detection is off, so a looping program can be followed
+/// round as many times as you like; a `Revisit` breakpoint stops it where the machine would
+/// otherwise have finished.
+pub struct Debugger {
+    machine: Machine,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Debugger {
+    pub fn new(mut machine: Machine) -> Debugger {
+        machine.set_loop_detection(false);
+        Debugger {
+            machine,
+            breakpoints: vec![Breakpoint::Revisit],
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Returns the breakpoint if there was one
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        if index < self.breakpoints.len() {
+            Some(self.breakpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// The first breakpoint which matches where the machine is now, given what the
+    /// accumulator was before the last step
+    fn hit(&self, acc_before: i64) -> Option<Breakpoint> {
+        let m = &self.machine;
+        self.breakpoints.iter().copied().find(|&b| match b {
+            Breakpoint::Ip(ip) => m.status() == Status::Running && m.ip() == ip as isize,
+            Breakpoint::Acc(acc) => m.acc() == acc && acc_before != acc,
+            Breakpoint::Revisit => m.about_to_revisit(),
+        })
+    }
+
+    /// Runs at least one instruction, then keeps going until a breakpoint matches, the program
+    /// finishes, or `limit` instructions have run (so a limit of 0 still runs one)
+    pub fn resume(&mut self, limit: Option<usize>) -> Stop {
+        let mut remaining = limit.unwrap_or(usize::MAX);
+        loop {
+            let acc_before = self.machine.acc();
+            if let Status::Finished(outcome) = self.machine.step() {
+                return Stop::Finished(outcome);
+            }
+            if let Some(breakpoint) = self.hit(acc_before) {
+                return Stop::Breakpoint(breakpoint);
+            }
+            remaining = remaining.saturating_sub(1);
+            if remaining == 0 {
+                return Stop::Limit;
+            }
+        }
+    }
+}
+
+/// How far `continue` goes before giving up, so a loop without a breakpoint can't hang
+const CONTINUE_LIMIT: usize = 1_000_000;
+
+const HELP: &str = "commands:
+  s [n]           step n instructions (default 1), showing each
+  c               continue to the next breakpoint
+  b <ip>          break before running the instruction at ip
+  b acc <value>   break when the accumulator becomes value
+  b revisit       break before running an instruction a second time
+  d <n>           delete breakpoint n
+  l               list breakpoints
+  p               show the machine state
+  t               toggle showing every instruction while continuing
+  q               quit";
+
+fn describe(machine: &Machine) -> String {
+    match (machine.status(), machine.current()) {
+        (Status::Running, Some(op)) => format!(
+            "ip {} ({}), acc {}, {} steps",
+            machine.ip(),
+            op,
+            machine.acc(),
+            machine.steps()
+        ),
+        (Status::Finished(outcome), _) => {
+            format!("finished: {:?}, after {} steps", outcome, machine.steps())
+        }
+        (Status::Running, None) => unreachable!(),
+    }
+}
+
+fn describe_stop(stop: Stop) -> String {
+    match stop {
+        Stop::Breakpoint(b) => format!("hit breakpoint: {}", b),
+        Stop::Finished(_) => "program finished".to_string(),
+        Stop::Limit => format!("stopped after {} steps", CONTINUE_LIMIT),
+    }
+}
+
+/// Runs the machine with tracing on only if the trace is going to be shown, giving back
+/// what was recorded. Nothing is kept between commands, however long the session goes on.
+fn traced<T>(
+    debugger: &mut Debugger,
+    on: bool,
+    run: impl FnOnce(&mut Debugger) -> T,
+) -> (T, Vec<TraceEntry>) {
+    debugger.machine_mut().set_tracing(on);
+    let result = run(debugger);
+    debugger.machine_mut().set_tracing(false);
+    (result, debugger.machine_mut().take_trace())
+}
+
+/// Reads commands one per line from `input` until `q` or the end, writing everything to
+/// `output`
+pub fn session<R: BufRead, W: Write>(
+    debugger: &mut Debugger,
+    input: R,
+    output: &mut W,
+) -> io::Result<()> {
+    let mut show_continue = false;
+
+    writeln!(output, "{}", describe(debugger.machine()))?;
+    write!(output, "> ")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let mut shown = Vec::new();
+
+        let reply = match *words.as_slice() {
+            [] => None,
+            ["q"] => break,
+            ["h"] | ["help"] => Some(HELP.to_string()),
+            ["s"] | ["s", _] => match words.get(1).map_or(Ok(1), |n| n.parse::<usize>()) {
+                Ok(n) => {
+                    let (_, trace) = traced(debugger, true, |d| d.machine_mut().run_until(Some(n)));
+                    shown = trace;
+                    Some(describe(debugger.machine()))
+                }
+                Err(_) => Some("usage: s [n]".to_string()),
+            },
+            ["c"] => {
+                let (stop, trace) =
+                    traced(debugger, show_continue, |d| d.resume(Some(CONTINUE_LIMIT)));
+                shown = trace;
+                Some(format!(
+                    "{}\n{}",
+                    describe_stop(stop),
+                    describe(debugger.machine())
+                ))
+            }
+            ["b", "revisit"] => {
+                debugger.add_breakpoint(Breakpoint::Revisit);
+                Some("breaking on revisits".to_string())
+            }
+            ["b", "acc", value] => match value.parse() {
+                Ok(acc) => {
+                    debugger.add_breakpoint(Breakpoint::Acc(acc));
+                    Some(format!("breaking when acc is {}", acc))
+                }
+                Err(_) => Some("usage: b acc <value>".to_string()),
+            },
+            ["b", ip] => match ip.parse() {
+                Ok(ip) => {
+                    debugger.add_breakpoint(Breakpoint::Ip(ip));
+                    Some(format!("breaking at ip {}", ip))
+                }
+                Err(_) => Some("usage: b <ip>".to_string()),
+            },
+            ["d", n] => match n.parse().ok().and_then(|n| debugger.remove_breakpoint(n)) {
+                Some(b) => Some(format!("deleted breakpoint: {}", b)),
+                None => Some(format!("no breakpoint {}", n)),
+            },
+            ["l"] => Some(
+                debugger
+                    .breakpoints()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, b)| format!("{}: {}", i, b))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+            .filter(|list| !list.is_empty())
+            .or_else(|| Some("no breakpoints".to_string())),
+            ["p"] => Some(describe(debugger.machine())),
+            ["t"] => {
+                show_continue = !show_continue;
+                Some(format!(
+                    "showing instructions while continuing: {}",
+                    if show_continue { "on" } else { "off" }
+                ))
+            }
+            _ => Some(format!("unknown command '{}'; try 'help'", line.trim())),
+        };
+
+        for entry in &shown {
+            writeln!(output, "{}", entry)?;
+        }
+        if let Some(reply) = reply {
+            writeln!(output, "{}", reply)?;
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+
+    writeln!(output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_program;
+    use super::*;
+
+    const SAMPLE: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = Debugger::new(Machine::new(parse_program(SAMPLE).unwrap()));
+        debugger.machine_mut().set_tracing(true);
+
+        assert_eq!(debugger.resume(None), Stop::Breakpoint(Breakpoint::Revisit));
+        assert_eq!((debugger.machine().ip(), debugger.machine().acc()), (1, 5));
+        let last = debugger.machine().trace().last().unwrap();
+        assert_eq!(last.to_string(), "    4  jmp -3     acc 5 -> 5");
+
+        debugger.remove_breakpoint(0);
+        debugger.add_breakpoint(Breakpoint::Acc(10));
+        assert_eq!(debugger.resume(None), Stop::Breakpoint(Breakpoint::Acc(10)));
+        debugger.add_breakpoint(Breakpoint::Ip(7));
+        assert_eq!(debugger.resume(None), Stop::Breakpoint(Breakpoint::Ip(7)));
+        assert_eq!(debugger.resume(Some(3)), Stop::Limit);
+        let steps = debugger.machine().steps();
+        assert_eq!(debugger.resume(Some(0)), Stop::Limit);
+        assert_eq!(debugger.machine().steps(), steps + 1);
+
+        let mut fixed = parse_program(SAMPLE).unwrap();
+        fixed[7] = fixed[7].flipped().unwrap();
+        let mut debugger = Debugger::new(Machine::new(fixed));
+        assert_eq!(
+            debugger.resume(None),
+            Stop::Finished(Outcome::Terminated(8))
+        );
+    }
+
+    #[test]
+    fn scripted_session() {
+        let mut debugger = Debugger::new(Machine::new(parse_program(SAMPLE).unwrap()));
+        let commands = "s 2\nb 6\nc\nl\nd 0\nc\nwhat\nt\nb revisit\nc\nq\np\n";
+        let mut output = Vec::new();
+        session(&mut debugger, commands.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "ip 0 (nop +0), acc 0, 0 steps",
+                ">     0  nop +0     acc 0 -> 0",
+                "    1  acc +1     acc 0 -> 1",
+                "ip 2 (jmp +4), acc 1, 2 steps",
+                "> breaking at ip 6",
+                "> hit breakpoint: ip 6",
+                "ip 6 (acc +1), acc 1, 3 steps",
+                "> 0: revisit",
+                "1: ip 6",
+                "> deleted breakpoint: revisit",
+                "> hit breakpoint: ip 6",
+                "ip 6 (acc +1), acc 6, 9 steps",
+                "> unknown command 'what'; try 'help'",
+                "> showing instructions while continuing: on",
+                "> breaking on revisits",
+                ">     6  acc +1     acc 6 -> 7",
+                "hit breakpoint: revisit",
+                "ip 7 (jmp -4), acc 7, 10 steps",
+                "> ",
+            ]
+        );
+        // Only what was shown was recorded, and none of it is kept
+        assert!(debugger.machine().trace().is_empty());
+    }
+}
@@ -53,7 +53,7 @@ fn run_10b_with_input(input: &str) -> u64 {
         }
     }
 
-    let phone_voltage = nums.get(nums.len() - 1).unwrap() + 3;
+    let phone_voltage = nums.last().unwrap() + 3;
 
     // (prev_voltage, arr_offset) -> num_combos
     type Cache = HashMap<(u64, usize), u64>;
@@ -28,7 +28,7 @@ enum Turn {
 mod parse {
     use super::{Dir, Move, Turn};
 
-    use crate::lib::{maybe_newline, parse_i64};
+    use crate::{maybe_newline, parse_i64};
 
     use nom::{
         character::complete::{anychar, char as exact_char},
@@ -22,7 +22,7 @@ fn parse_a(input: &str) -> (usize, Vec<usize>) {
 fn run_13a_with_input(input: &str) -> usize {
     let (start_time, bus_ids) = parse_a(input);
 
-    let mut least_wait = usize::max_value();
+    let mut least_wait = usize::MAX;
     let mut running_score = 0;
 
     for bus_id in bus_ids {
@@ -89,7 +89,7 @@ fn run_13b_with_input(input: &str) -> usize {
     }
 
     // Pretty sure this doesn't matter but why not try, right?
-    running_index = running_index % prime_prods;
+    running_index %= prime_prods;
 
     running_index
 }
@@ -1,3 +1,17 @@
+pub mod day01;
+pub mod day02;
+pub mod day03;
+pub mod day04;
+pub mod day05;
+pub mod day06;
+pub mod day07;
+pub mod day08;
+pub mod day09;
+pub mod day10;
+pub mod day11;
+pub mod day12;
+pub mod day13;
+
 use nom::{
     branch::alt,
     character::complete::{char as exact_char, digit1, newline},
@@ -32,3 +46,92 @@ pub fn parse_lines<T, F: FnMut(&str) -> IResult<&str, T>>(
     let (input, _) = pair(opt(newline), eof)(input)?;
     Ok((input, out))
 }
+
+/// Tiny seeded xorshift generator, for the places where we want reproducible
+/// "random" data without pulling in a dependency.
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> XorShift {
+        // xorshift gets stuck on zero, so scramble the seed first (splitmix64 finalizer)
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        XorShift {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+
+    /// Uniform-ish value in `0..n`; panics if `n` is zero
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0, "Cannot pick from an empty range");
+        (self.next_u64() % (n as u64)) as usize
+    }
+
+    /// Uniform-ish value in `lo..=hi`
+    pub fn between(&mut self, lo: usize, hi: usize) -> usize {
+        lo + self.below(hi - lo + 1)
+    }
+}
+
+/// Quotes and escapes a string for hand-written JSON output
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Reads a whole file, with an error message fit for the command line
+pub fn read_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))
+}
+
+/// Splits `--input <file>` from the other arguments, reading the file, or giving back
+/// `default` (the puzzle input) if there isn't one
+pub fn read_input_arg(
+    args: &[String],
+    default: &str,
+    usage: &str,
+) -> Result<(String, Vec<String>), String> {
+    let mut input_file = None;
+    let mut rest = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input_file = Some(args.next().ok_or(usage)?),
+            _ => rest.push(arg.clone()),
+        }
+    }
+
+    let input = match input_file {
+        Some(path) => read_file(path)?,
+        None => default.to_string(),
+    };
+    Ok((input, rest))
+}
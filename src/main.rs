@@ -1,76 +1,89 @@
 use std::env;
 
-mod day01;
-mod day02;
-mod day03;
-mod day04;
-mod day05;
-mod day06;
-mod day07;
-mod day08;
-mod day09;
-mod day10;
-mod day11;
-mod day12;
-mod day13;
-
-mod lib;
+use aoc_2020::{
+    day01, day02, day03, day04, day05, day06, day07, day08, day09, day10, day11, day12, day13,
+};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 2 {
-        println!("Usage: [run] [problemNumber]");
+    if args.len() < 2 {
+        println!("Usage: [run] [problemNumber] [extra args]");
         println!("Example: cargo run --release -- 1a");
     }
 
+    // Everything after the problem number, for the subcommands which take arguments
+    let extra = args.get(2..).unwrap_or(&[]);
+
     let start = std::time::Instant::now();
 
-    let out: String = match args.get(1).unwrap().as_str() {
-        "1a" => day01::run_1a().to_string(),
-        "1b" => day01::run_1b().to_string(),
+    // Subcommand errors (usage, bad input) go to stderr, so redirected output stays clean
+    let out: Result<String, String> = match args.get(1).unwrap().as_str() {
+        "1a" => Ok(day01::run_1a().to_string()),
+        "1b" => Ok(day01::run_1b().to_string()),
 
-        "2a" => day02::run_2a().to_string(),
-        "2b" => day02::run_2b().to_string(),
+        "2a" => Ok(day02::run_2a().to_string()),
+        "2b" => Ok(day02::run_2b().to_string()),
+        "2gen" => day02::run_2gen(extra),
 
-        "3a" => day03::run_3a().to_string(),
-        "3b" => day03::run_3b().to_string(),
+        "3a" => Ok(day03::run_3a().to_string()),
+        "3b" => Ok(day03::run_3b().to_string()),
+        "3rank" => day03::run_3rank(extra),
+        "3path" => day03::run_3path(extra),
+        "3route" => day03::run_3route(extra),
 
-        "4a" => day04::run_4a().to_string(),
-        "4b" => day04::run_4b().to_string(),
+        "4a" => Ok(day04::run_4a().to_string()),
+        "4b" => Ok(day04::run_4b().to_string()),
+        "4check" => day04::run_4check(extra),
 
-        "5a" => day05::run_5a().to_string(),
-        "5b" => day05::run_5b().to_string(),
+        "5a" => Ok(day05::run_5a().to_string()),
+        "5b" => Ok(day05::run_5b().to_string()),
+        "5encode" => day05::run_5encode(extra),
+        "5check" => day05::run_5check(extra),
+        "5map" => Ok(day05::run_5map()),
 
-        "6a" => day06::run_6a().to_string(),
-        "6b" => day06::run_6b().to_string(),
+        "6a" => Ok(day06::run_6a().to_string()),
+        "6b" => Ok(day06::run_6b().to_string()),
+        "6query" => day06::run_6query(extra),
+        "6stats" => day06::run_6stats(extra),
 
-        "7a" => day07::run_7a().to_string(),
-        "7b" => day07::run_7b().to_string(),
+        "7a" => Ok(day07::run_7a().to_string()),
+        "7b" => Ok(day07::run_7b().to_string()),
+        "7bag" => day07::run_7bag(extra),
+        "7check" => day07::run_7check(extra),
+        "7export" => day07::run_7export(extra),
 
-        "8a" => day08::run_8a().to_string(),
-        "8b" => day08::run_8b().to_string(),
+        "8a" => Ok(day08::run_8a().to_string()),
+        "8b" => Ok(day08::run_8b().to_string()),
+        "8repair" => day08::run_8repair(extra),
+        "8debug" => day08::run_8debug(extra),
 
-        "9a" => day09::run_9a().to_string(),
-        "9b" => day09::run_9b().to_string(),
+        "9a" => Ok(day09::run_9a().to_string()),
+        "9b" => Ok(day09::run_9b().to_string()),
 
-        "10a" => day10::run_10a().to_string(),
-        "10b" => day10::run_10b().to_string(),
+        "10a" => Ok(day10::run_10a().to_string()),
+        "10b" => Ok(day10::run_10b().to_string()),
 
-        "11a" => day11::run_11a().to_string(),
-        "11b" => day11::run_11b().to_string(),
+        "11a" => Ok(day11::run_11a().to_string()),
+        "11b" => Ok(day11::run_11b().to_string()),
 
-        "12a" => day12::run_12a().to_string(),
-        "12b" => day12::run_12b().to_string(),
+        "12a" => Ok(day12::run_12a().to_string()),
+        "12b" => Ok(day12::run_12b().to_string()),
 
-        "13a" => day13::run_13a().to_string(),
-        "13b" => day13::run_13b().to_string(),
+        "13a" => Ok(day13::run_13a().to_string()),
+        "13b" => Ok(day13::run_13b().to_string()),
 
-        other => {
-            format!("Unrecognized problem number {}", other)
-        }
+        other => Err(format!("Unrecognized problem number {}", other)),
     };
 
-    println!("{}", out);
-    println!("Process took {:.5} seconds", start.elapsed().as_secs_f32());
+    match out {
+        Ok(out) => {
+            println!("{}", out);
+            eprintln!("Process took {:.5} seconds", start.elapsed().as_secs_f32());
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }